@@ -0,0 +1,226 @@
+/* Interactive shell over a FileSystem, e.g. `cargo run --bin shell -- my_fs`. Type `help` for the commands.
+   Paths follow the FileSystem rules: relative to the current directory, or to the root when starting with '/'. */
+use std::borrow::Cow;
+use std::path::Path;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use file_system_manipulation::file_system::FileSystem;
+use file_system_manipulation::file_system::dir::{Dir, Node};
+use file_system_manipulation::file_system::dir::file::CustomError;
+use file_system_manipulation::file_system::walk::Walk;
+
+const COMMANDS: [(&str, &str); 16] = [
+    ("ls", "ls [path]                list the content of a dir"),
+    ("cd", "cd [path]                change the current dir, the root by default"),
+    ("pwd", "pwd                      print the current dir"),
+    ("tree", "tree [path]              print the whole tree below a dir"),
+    ("mkdir", "mkdir <path>             make a dir"),
+    ("rm", "rm [-r] <path>           remove a file or a link, or a whole dir with -r"),
+    ("rmdir", "rmdir <path>             remove an empty dir"),
+    ("ln", "ln <target> <path>       make a link to 'target'"),
+    ("cat", "cat <path>               print the content of a file as text"),
+    ("hexdump", "hexdump <path>           print the content of a file as hex and ascii"),
+    ("find", "find <query>             search below the current dir, e.g. find glob:*.txt AND larger:10"),
+    ("scan", "scan <disk dir>          replace the tree with a disk directory"),
+    ("save", "save <file>              save the tree in a snapshot file"),
+    ("load", "load <file>              replace the tree with a snapshot file"),
+    ("help", "help                     print this list"),
+    ("exit", "exit                     quit the shell"),
+];
+
+enum ShellError {
+    Usage(&'static str),
+    NotADir(String),
+    NotAFile(String),
+    IsADir(String),
+    Fs { command: String, path: String, error: CustomError }, // the command and path are filled in by run
+}
+impl std::fmt::Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::Usage(usage) => write!(f, "usage: {}", usage),
+            ShellError::NotADir(path) => write!(f, "{}: not a directory", path),
+            ShellError::NotAFile(path) => write!(f, "{}: not a file", path),
+            ShellError::IsADir(path) => write!(f, "{}: is a directory, use rm -r to remove it with its content", path),
+            ShellError::Fs { command, path, error } => write!(f, "{}: {}: {}", command, path, error),
+        }
+    }
+}
+impl From<CustomError> for ShellError {
+    fn from(error: CustomError) -> Self {
+        ShellError::Fs { command: String::new(), path: String::new(), error }
+    }
+}
+
+fn usage(command: &str) -> ShellError {
+    let line = COMMANDS.iter().find(|(name, _)| *name == command).map(|(_, line)| *line).unwrap_or("help");
+    ShellError::Usage(line.split("  ").next().unwrap_or(line))
+}
+
+// the FileSystem lives in the line editor helper, so that completion always sees the current tree
+struct Shell {
+    fs: FileSystem,
+}
+impl Shell {
+    fn dir(&self, path: &str) -> Result<&Dir, ShellError> {
+        let resolved = self.fs.resolve_target(path)?;
+        self.fs.get_root().get_dir(&resolved).ok_or_else(|| ShellError::NotADir(path.to_string()))
+    }
+    fn content(&self, path: &str) -> Result<Vec<u8>, ShellError> {
+        let resolved = self.fs.resolve_target(path)?;
+        match self.fs.get_root().get_node(&resolved) {
+            Some(Node::File(file)) => Ok(file.read_content()?.into_owned()),
+            _ => Err(ShellError::NotAFile(path.to_string())),
+        }
+    }
+
+    // returns false when the shell has to quit
+    fn run(&mut self, line: &str) -> Result<bool, ShellError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else { return Ok(true) };
+        self.execute(command, args).map_err(|e| match e {
+            ShellError::Fs { error, .. } => {
+                let path = if command == "find" { args.join(" ") } else { args.last().unwrap_or(&".").to_string() };
+                ShellError::Fs { command: command.to_string(), path, error }
+            }
+            e => e,
+        })
+    }
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<bool, ShellError> {
+        match (command, args) {
+            ("ls", [] | [_]) => {
+                for child in self.dir(args.first().unwrap_or(&"."))?.get_children() {
+                    println!("{}", describe(child));
+                }
+            }
+            ("cd", []) => self.fs.cd("/")?,
+            ("cd", [path]) => self.fs.cd(path)?,
+            ("pwd", []) => println!("{}", self.fs.pwd()),
+            ("tree", [] | [_]) => {
+                let dir = self.dir(args.first().unwrap_or(&"."))?;
+                println!("{}", dir.get_name());
+                for (_, depth, node) in Walk::new(dir) {
+                    println!("{}{}", "    ".repeat(depth), describe(node));
+                }
+            }
+            ("mkdir", [path]) => self.fs.mk_dir(path)?,
+            ("rm", [path]) if matches!(self.fs.get_root().get_node(&self.fs.resolve(path)?), Some(Node::Dir(_))) => {
+                return Err(ShellError::IsADir(path.to_string()));
+            }
+            ("rm", [path]) => self.fs.rm_file(path)?,
+            ("rm", ["-r", path]) => println!("{}", self.fs.rm_dir_all(path)?),
+            ("rmdir", [path]) => self.fs.rm_dir(path)?,
+            ("ln", [target, path]) => self.fs.symlink(path, target)?,
+            ("cat", [path]) => println!("{}", String::from_utf8_lossy(&self.content(path)?)),
+            ("hexdump", [path]) => hexdump(&self.content(path)?),
+            ("find", [_, ..]) => {
+                let query = args.join(" ");
+                for node in self.fs.search_from(".", &[&query], None)?.get_nodes() {
+                    println!("{}", node.get_name());
+                }
+            }
+            ("scan", [path]) => self.fs = FileSystem::from_dir(path)?,
+            ("save", [path]) => self.fs.save(path)?,
+            ("load", [path]) => self.fs = FileSystem::load(path)?,
+            ("help", []) => COMMANDS.iter().for_each(|(_, line)| println!("{}", line)),
+            ("exit" | "quit", []) => return Ok(false),
+            _ if COMMANDS.iter().any(|(name, _)| *name == command) => return Err(usage(command)),
+            _ => println!("{}: unknown command, type 'help' for the list", command),
+        }
+        Ok(true)
+    }
+}
+
+fn leaf(node: &Node) -> Cow<'_, str> {
+    Path::new(node.get_name()).file_name().map(|leaf| leaf.to_string_lossy()).unwrap_or_default()
+}
+
+fn describe(node: &Node) -> String {
+    match node {
+        Node::Dir(_) => format!("{}/", leaf(node)),
+        Node::File(file) => format!("{}  ({} bytes, {})", leaf(node), file.get_size(), file.get_filetype()),
+        Node::Symlink(link) => format!("{} -> {}", leaf(node), link.get_target().display()),
+    }
+}
+
+fn hexdump(content: &[u8]) {
+    for (i, line) in content.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = line.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+        println!("{:08x}  {:<47}  |{}|", i * 16, hex.join(" "), ascii);
+    }
+}
+
+impl Completer for Shell {
+    type Candidate = Pair;
+
+    // the first word is completed as a command, the others as paths of the tree
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if line[..start].trim().is_empty() {
+            let commands = COMMANDS.iter()
+                .filter(|(name, _)| name.starts_with(word))
+                .map(|(name, _)| Pair { display: name.to_string(), replacement: format!("{} ", name) })
+                .collect();
+            return Ok((start, commands));
+        }
+        let (dir, prefix) = match word.rfind('/') {
+            Some(0) => ("/", &word[1..]),
+            Some(i) => (&word[..i], &word[i + 1..]),
+            None => (".", word),
+        };
+        let Ok(dir) = self.dir(dir) else { return Ok((pos, vec![])) };
+        let mut paths: Vec<Pair> = dir.get_children().iter()
+            .filter(|child| leaf(child).starts_with(prefix))
+            .map(|child| {
+                let name = leaf(child).to_string();
+                let replacement = if let Node::Dir(_) = child { format!("{}/", name) } else { name.clone() };
+                Pair { display: name, replacement }
+            })
+            .collect();
+        paths.sort_by(|a, b| a.display.cmp(&b.display));
+        Ok((pos - prefix.len(), paths))
+    }
+}
+impl Hinter for Shell {
+    type Hint = String;
+}
+impl Highlighter for Shell {}
+impl Validator for Shell {}
+impl Helper for Shell {}
+
+fn main() -> rustyline::Result<()> {
+    let mut fs = FileSystem::new();
+    if let Some(path) = std::env::args().nth(1) {
+        match FileSystem::from_dir(&path) {
+            Ok(scanned) => fs = scanned,
+            Err(e) => eprintln!("cannot scan {}: {}", path, e),
+        }
+    }
+    let mut editor: Editor<Shell, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(Shell { fs }));
+    loop {
+        let shell = editor.helper_mut().expect("the helper is set before the loop");
+        let prompt = format!("{}> ", shell.fs.pwd());
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        editor.add_history_entry(line.as_str())?;
+        let shell = editor.helper_mut().expect("the helper is set before the loop");
+        match shell.run(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+    Ok(())
+}
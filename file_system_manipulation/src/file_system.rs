@@ -1,16 +1,42 @@
+pub mod diff;
 pub mod dir;
+pub mod duplicates;
+pub mod journal;
+pub mod query;
+pub mod shared;
+pub mod snapshot;
+pub mod sync;
+pub mod transaction;
+pub mod versions;
+pub mod walk;
+pub mod watch;
 
-use std::cell::RefCell;
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
-use std::path::{PathBuf};
-use crate::file_system::dir::{Dir, Node};
-use crate::file_system::dir::file::{CustomError, File, FileType};
-use crate::file_system::dir::file::CustomError::{FileOrDirNameNotFound, InvalidQuery};
+use std::path::{Component, Path, PathBuf};
+use crate::file_system::dir::{Dir, Node, RemoveSummary, ScanOptions};
+use crate::file_system::dir::file::{CustomError, File, FileType, NodeMetadata, timestamp_to_u64};
+use crate::file_system::dir::file::CustomError::FileOrDirNameNotFound;
+use crate::file_system::dir::symlink::Symlink;
+use crate::file_system::sync::{SyncAction, sync_dir};
+use crate::file_system::journal::{Journal, Operation};
+use crate::file_system::transaction::Transaction;
+use crate::file_system::diff::{Diff, DiffOptions};
+use crate::file_system::duplicates::Duplicates;
+use crate::file_system::versions::Version;
+use crate::file_system::walk::Walk;
+use crate::file_system::watch::Watcher;
+use crate::file_system::query::{Expression, QueryError, QueryErrorReason, glob_matches};
+use regex::Regex;
 
 #[derive(Default)]
 pub struct MatchResult<'a> {
     queries: Vec<&'a str>, // query matchate
-    nodes: Vec<&'a mut Node>
+    nodes: Vec<&'a Node>
+}
+impl<'a> MatchResult<'a> {
+    pub fn get_queries(&self) -> &Vec<&'a str> { &self.queries }
+    pub fn get_nodes(&self) -> &Vec<&'a Node> { &self.nodes }
 }
 impl Display for MatchResult<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -30,160 +56,740 @@ impl Display for MatchResult<'_> {
 }
 
 pub enum Queries<'a>{
-    Name(&'a str, &'a str),
-    Content(&'a str, &'a str),
-    Larger(&'a str, usize),
-    Smaller(&'a str, usize),
-    Newer(&'a str, u64),
-    Older(&'a str, u64)
+    Name(&'a str),
+    Glob(&'a str),
+    Regex(Regex),
+    Content(&'a str),
+    Type(&'a str),
+    Hash(&'a str), // a prefix of the hex SHA-256 of the content, like short git hashes
+    Larger(usize),
+    Smaller(usize),
+    Newer(u64),
+    Older(u64),
+    ModifiedAfter(u64),
+    ModifiedBefore(u64),
+    AccessedAfter(u64),
+    AccessedBefore(u64),
+    Perm(u32),
+    Owner(u32),
+    Group(u32)
 }
 impl<'a> Queries<'a>{
-    fn to_str(&self) -> &'a str {
-        match self {
-            Self::Name(string, _) => string,
-            Self::Content(string, _) => string,
-            Self::Larger(string, _) => string,
-            Self::Smaller(string, _) => string,
-            Self::Newer(string, _) => string,
-            Self::Older(string, _) => string,
-        }
+    pub fn parse(query_string: &'a str) -> Result<Queries<'a>, QueryError> {
+        let (query_type, raw_value) = query_string
+            .split_once(':')
+            .ok_or_else(|| QueryError::new(query_string, 0, QueryErrorReason::MissingColon))?;
+        let value_position = query_type.len() + 1;
+        let not_a_number = || QueryError::new(query_string, value_position, QueryErrorReason::NotANumber(raw_value.to_string()));
+        let query_value = raw_value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(raw_value);
+        let enum_type_query = match query_type {
+            "name" if query_value.contains(['*', '?', '[']) => Queries::Glob(query_value),
+            "name" => Queries::Name(query_value),
+            "glob" => Queries::Glob(query_value),
+            "regex" => Queries::Regex(Regex::new(query_value).map_err(|e| {
+                QueryError::new(query_string, value_position, QueryErrorReason::InvalidPattern(e.to_string()))
+            })?),
+            "content" => Queries::Content(query_value),
+            "type" if FileType::NAMES.contains(&query_value) => Queries::Type(query_value),
+            "type" => return Err(QueryError::new(query_string, value_position, QueryErrorReason::UnknownFileType(query_value.to_string()))),
+            "hash" if !query_value.is_empty() && query_value.len() <= 64 && query_value.chars().all(|c| c.is_ascii_hexdigit()) => Queries::Hash(query_value),
+            "hash" => return Err(QueryError::new(query_string, value_position, QueryErrorReason::NotAHash(query_value.to_string()))),
+            "larger" => Queries::Larger(query_value.parse::<usize>().map_err(|_| not_a_number())?),
+            "smaller" => Queries::Smaller(query_value.parse::<usize>().map_err(|_| not_a_number())?),
+            "newer" => Queries::Newer(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "older" => Queries::Older(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "modified-after" => Queries::ModifiedAfter(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "modified-before" => Queries::ModifiedBefore(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "accessed-after" => Queries::AccessedAfter(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "accessed-before" => Queries::AccessedBefore(query_value.parse::<u64>().map_err(|_| not_a_number())?),
+            "perm" => Queries::Perm(u32::from_str_radix(query_value, 8).map_err(|_| not_a_number())?),
+            "owner" => Queries::Owner(query_value.parse::<u32>().map_err(|_| not_a_number())?),
+            "group" => Queries::Group(query_value.parse::<u32>().map_err(|_| not_a_number())?),
+            &_ => return Err(QueryError::new(query_string, 0, QueryErrorReason::UnknownKey(query_type.to_string())))
+        };
+        Ok(enum_type_query)
     }
     pub fn matches(&self, node: &Node) -> bool {
         match node {
             Node::File(file) => self.match_for_file(file),
-            Node::Dir(dir) => self.match_for_dir(&dir.borrow()),
+            Node::Dir(dir) => self.match_for_dir(dir),
+            Node::Symlink(link) => self.match_for_symlink(link),
         }
     }
     fn match_for_file(&self, file: &File) -> bool {
         match self {
-            Queries::Name(_, name) => file.get_name().contains(name),
-            Queries::Content(_, content) => {
-                if *file.get_filetype() == FileType::Text {
-                    let file_contents = match std::str::from_utf8(file.get_content()) {
-                        Ok(content) => content,
-                        Err(_) => return false,
-                    };
-                    file_contents.contains(content)
-                } else {
-                    false
+            Queries::Name(name) => file.get_name().contains(name),
+            Queries::Glob(_) | Queries::Regex(_) => self.match_name_pattern(file.get_name()),
+            Queries::Content(content) => {
+                match file.get_filetype() {
+                    FileType::Text(encoding) => encoding.encode(content).is_some_and(|needle| file.content_contains(&needle).unwrap_or(false)),
+                    FileType::Binary(_) => false
                 }
             }
-            Queries::Larger(_, size) => file.get_content().len() > *size,
-            Queries::Smaller(_, size) => file.get_content().len() < *size,
-            Queries::Newer(_, time) => file.get_creation_time() > *time,
-            Queries::Older(_, time) => file.get_creation_time() < *time,
+            Queries::Type(name) => file.get_filetype().has_name(name),
+            Queries::Hash(prefix) => file.get_hash().is_ok_and(|hash| hash[..prefix.len()].eq_ignore_ascii_case(prefix)),
+            Queries::Larger(size) => file.get_size() > *size as u64,
+            Queries::Smaller(size) => file.get_size() < *size as u64,
+            Queries::Newer(time) => file.get_creation_time() > *time,
+            Queries::Older(time) => file.get_creation_time() < *time,
+            _ => self.match_metadata(file.get_metadata()),
+        }
+    }
+    // mode, uid and gid never match where the system does not provide them
+    fn match_metadata(&self, metadata: &NodeMetadata) -> bool {
+        match self {
+            Queries::ModifiedAfter(time) => metadata.modified_time > *time,
+            Queries::ModifiedBefore(time) => metadata.modified_time < *time,
+            Queries::AccessedAfter(time) => metadata.accessed_time > *time,
+            Queries::AccessedBefore(time) => metadata.accessed_time < *time,
+            Queries::Perm(perm) => metadata.mode.is_some_and(|mode| mode & 0o7777 == *perm),
+            Queries::Owner(uid) => metadata.uid == Some(*uid),
+            Queries::Group(gid) => metadata.gid == Some(*gid),
+            _ => false
+        }
+    }
+    // patterns are tested against both the leaf name and the full path, always '/' separated
+    fn match_name_pattern(&self, name: &str) -> bool {
+        let full_path = name.replace('\\', "/");
+        let leaf = full_path.rsplit('/').next().unwrap_or_default();
+        match self {
+            Queries::Glob(pattern) => glob_matches(pattern, leaf) || glob_matches(pattern, &full_path),
+            Queries::Regex(regex) => regex.is_match(leaf) || regex.is_match(&full_path),
+            _ => false
         }
     }
     fn match_for_dir(&self, dir: &Dir) -> bool {
         match self {
-            Queries::Name(_, name) => dir.get_name().contains(name),
-            Queries::Content(_, _) => false,
-            Queries::Larger(_, _) => false,
-            Queries::Smaller(_, _) => false,
-            Queries::Newer(_, time) => dir.get_creation_time() > *time,
-            Queries::Older(_, time) => dir.get_creation_time() < *time,
+            Queries::Name(name) => dir.get_name().contains(name),
+            Queries::Glob(_) | Queries::Regex(_) => self.match_name_pattern(dir.get_name()),
+            Queries::Content(_) => false,
+            Queries::Type(_) => false,
+            Queries::Hash(_) => false,
+            Queries::Larger(_) => false,
+            Queries::Smaller(_) => false,
+            Queries::Newer(time) => dir.get_creation_time() > *time,
+            Queries::Older(time) => dir.get_creation_time() < *time,
+            _ => self.match_metadata(dir.get_metadata()),
         }
     }
+    // a link matches by its own name and times, never by what it points to
+    fn match_for_symlink(&self, link: &Symlink) -> bool {
+        match self {
+            Queries::Name(name) => link.get_name().contains(name),
+            Queries::Glob(_) | Queries::Regex(_) => self.match_name_pattern(link.get_name()),
+            Queries::Content(_) | Queries::Type(_) | Queries::Hash(_) | Queries::Larger(_) | Queries::Smaller(_) => false,
+            Queries::Newer(time) => link.get_creation_time() > *time,
+            Queries::Older(time) => link.get_creation_time() < *time,
+            _ => self.match_metadata(link.get_metadata()),
+        }
+    }
+}
+
+pub struct CopyOptions {
+    pub recursive: bool, // needed to copy directories
+    pub preserve_creation_time: bool, // otherwise the copies are created now
 }
 
+// like on linux, a longer chain of links is taken as a loop
+const MAX_SYMLINK_HOPS: usize = 40;
+
+#[derive(Default)]
 pub struct FileSystem {
-    root: Dir
+    root: Dir,
+    cwd: Vec<String>, // components of the current directory, below the root
+    journal: Journal,
+    versions: Vec<Version> // named snapshots of the tree, oldest first
 }
 impl Display for FileSystem{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "FileSystem: \n{}", self.root)
     }
 }
-#[cfg(target_os = "windows")]
-impl<'b> FileSystem{
+impl FileSystem{
     pub fn new() -> Self{
-        FileSystem{
-            root: Dir::default()
-        }
+        FileSystem::default()
     }
     pub fn from_dir(path: &str) -> Result<FileSystem, CustomError>{
+        FileSystem::from_dir_with(path, &ScanOptions::default())
+    }
+    pub fn from_dir_with(path: &str, options: &ScanOptions) -> Result<FileSystem, CustomError>{
         let mut fs = FileSystem::new();
-        fs.root = Dir::new(path)?;
+        fs.root = Dir::scan(path, options)?;
         Ok(fs)
     }
     pub fn get_root(&self) -> &Dir {&self.root}
+    /* Turns any path accepted by the FileSystem API into the full path stored in the tree:
+        - a path starting with the root name is a full path, e.g. "my_fs/folder0_0"
+        - a path starting with a separator is relative to the root, e.g. "/folder0_0"
+        - any other path is relative to the current directory, e.g. "../folder0_0"
+       both '/' and '\' are separators, empty and "." components are dropped and ".." never goes above the root.
+       Links met along the way are followed, the same rules applying to their targets, except for the last
+       component: the result is the link itself, so that it can be moved or removed. */
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, CustomError> {
+        self.resolve_links(path, false)
+    }
+    // like resolve, but a link in the last component is followed too
+    pub fn resolve_target(&self, path: &str) -> Result<PathBuf, CustomError> {
+        self.resolve_links(path, true)
+    }
+    fn resolve_links(&self, path: &str, follow_last: bool) -> Result<PathBuf, CustomError> {
+        let full_path = |components: &[String]| {
+            let mut full_path = PathBuf::from(self.root.get_name());
+            full_path.extend(components);
+            full_path
+        };
+        let (mut resolved, mut pending) = self.split_path(path, &self.cwd)?;
+        pending.reverse();
+        let mut hops = 0;
+        while let Some(name) = pending.pop() {
+            if name == ".." {
+                resolved.pop();
+                continue;
+            }
+            resolved.push(name);
+            if pending.is_empty() && !follow_last {
+                break;
+            }
+            if let Some(Node::Symlink(link)) = self.root.get_node(&full_path(&resolved)) {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(CustomError::SymlinkLoop);
+                }
+                resolved.pop();
+                let (start, components) = self.split_path(link.get_target().to_str().ok_or(FileOrDirNameNotFound)?, &resolved)?;
+                resolved = start;
+                pending.extend(components.into_iter().rev());
+            }
+        }
+        Ok(full_path(&resolved))
+    }
+    // the components 'path' starts from, below the root, and its own components; relative paths start from 'base'
+    fn split_path(&self, path: &str, base: &[String]) -> Result<(Vec<String>, Vec<String>), CustomError> {
+        let root = Path::new(self.root.get_name());
+        let path = path.replace('\\', "/");
+        let path = Path::new(&path);
+        let (start, relative) = match path.strip_prefix(root) {
+            Ok(relative) if !root.as_os_str().is_empty() => (vec![], relative),
+            _ if path.has_root() => (vec![], path),
+            _ => (base.to_vec(), path)
+        };
+        let mut components = vec![];
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => components.push(name.to_str().ok_or(FileOrDirNameNotFound)?.to_string()),
+                Component::ParentDir => components.push("..".to_string()),
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+        Ok((start, components))
+    }
+    pub fn cd(&mut self, path: &str) -> Result<(), CustomError>{
+        let path = self.resolve_target(path)?;
+        self.root.get_dir(&path).ok_or(FileOrDirNameNotFound)?;
+        self.set_cwd(&path)
+    }
+    fn set_cwd(&mut self, path: &Path) -> Result<(), CustomError>{
+        self.cwd = path
+            .strip_prefix(self.root.get_name())
+            .map_err(|_| FileOrDirNameNotFound)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        Ok(())
+    }
+    pub fn pwd(&self) -> String {
+        let mut cwd = PathBuf::from(self.root.get_name());
+        cwd.extend(&self.cwd);
+        cwd.to_string_lossy().to_string()
+    }
     pub fn mk_dir(&mut self, path: &str) -> Result<(), CustomError>{
-        if self.root.is_empty() {
+        if self.root.get_name().is_empty() {
+            // the first dir of a new FileSystem is its root, there is nothing to journal before it
             self.root = Dir::new(path)?;
-        }else{
-            self.root.mk_dir(&PathBuf::from(path))?;
+            self.cwd.clear();
+            return Ok(());
         }
-        Ok(())
+        let path = self.resolve(path)?;
+        self.record(format!("mk_dir {}", path.display()), &[&path], false, |fs| fs.root.mk_dir(&path))
     }
     pub fn rm_dir(&mut self, path: &str) -> Result<(), CustomError>{
         if self.root.is_empty() {
             return Err(FileOrDirNameNotFound);
         }
-        self.root.rm_dir(&PathBuf::from(path))?;
-        Ok(())
+        let path = self.resolve(path)?;
+        self.record(format!("rm_dir {}", path.display()), &[&path], true, |fs| {
+            fs.root.rm_dir(&path, false)?;
+            fs.fix_cwd();
+            Ok(())
+        })
+    }
+    pub fn rm_dir_all(&mut self, path: &str) -> Result<RemoveSummary, CustomError>{
+        if self.root.is_empty() {
+            return Err(FileOrDirNameNotFound);
+        }
+        let path = self.resolve(path)?;
+        self.record(format!("rm_dir_all {}", path.display()), &[&path], true, |fs| {
+            let summary = fs.root.rm_dir(&path, true)?;
+            fs.fix_cwd();
+            Ok(summary)
+        })
     }
     pub fn new_file(&mut self, path: &str, file: File) -> Result<(), CustomError>{
-        self.root.new_file(&PathBuf::from(&path), &file)?;
-        Ok(())
+        let path = self.resolve(path)?;
+        self.record(format!("new_file {}", path.display()), &[&path], false, |fs| fs.root.new_file(&path, &file))
+    }
+    // 'target' is stored as given and only resolved when the link is followed
+    pub fn symlink(&mut self, path: &str, target: &str) -> Result<(), CustomError>{
+        let path = self.resolve(path)?;
+        self.record(format!("symlink {} -> {}", path.display(), target), &[&path], false, |fs| fs.root.new_symlink(&path, Path::new(target)))
     }
     pub fn rm_file(&mut self, path: &str) -> Result<(), CustomError>{
         if self.root.is_empty() {
             return Err(FileOrDirNameNotFound);
         }
-        self.root.rm_file(&PathBuf::from(&path))?;
-        Ok(())
+        let path = self.resolve(path)?;
+        self.record(format!("rm_file {}", path.display()), &[&path], true, |fs| fs.root.rm_file(&path))
     }
     pub fn get_file(&mut self, path: &str) -> Option<&mut File>{
         if self.root.is_empty() {
             return None;
         }
-        self.root.get_file(&PathBuf::from(&path))
+        let path = self.resolve_target(path).ok()?;
+        self.root.get_file(&path)
+    }
+    // a link in the last component is followed
+    pub fn get_node(&self, path: &str) -> Option<&Node>{
+        self.root.get_node(&self.resolve_target(path).ok()?)
+    }
+    pub fn mv(&mut self, src: &str, dst: &str) -> Result<(), CustomError>{
+        let (src, dst) = (&self.resolve(src)?, &self.resolve(dst)?);
+        self.record(format!("mv {} -> {}", src.display(), dst.display()), &[src, dst], false, |fs| fs.move_node(src, dst))
+    }
+    fn move_node(&mut self, src: &Path, dst: &Path) -> Result<(), CustomError>{
+        let src_parent = src.parent().ok_or(FileOrDirNameNotFound)?;
+        let dst_parent = dst.parent().ok_or(FileOrDirNameNotFound)?;
+        let src_is_dir = match self.root.get_dir(src_parent) {
+            Some(parent) if parent.contains(src) => self.root.get_dir(src).is_some(),
+            _ => return Err(FileOrDirNameNotFound)
+        };
+        if src_is_dir && dst.starts_with(src) {
+            return Err(CustomError::MoveIntoItself);
+        }
+        match self.root.get_dir(dst_parent) {
+            Some(parent) if parent.contains(dst) => return Err(CustomError::DirOrFileAlreadyExists),
+            Some(_) => {},
+            None => return Err(FileOrDirNameNotFound)
+        }
+        // everything is checked, so the tree cannot be left half moved
+        let mut node = self.root.get_dir_mut(src_parent).and_then(|parent| parent.take_child(src)).ok_or(FileOrDirNameNotFound)?;
+        node.set_path(dst)?;
+        self.root.get_dir_mut(dst_parent).ok_or(FileOrDirNameNotFound)?.add_child(node);
+        // the current dir moves along when it is the moved dir or inside it
+        let cwd = PathBuf::from(self.pwd());
+        match cwd.strip_prefix(src) {
+            Ok(rest) => self.set_cwd(&dst.join(rest)),
+            Err(_) => Ok(()),
+        }
+    }
+    pub fn copy(&mut self, src: &str, dst: &str, recursive: bool) -> Result<(), CustomError>{
+        self.copy_with(src, dst, &CopyOptions { recursive, preserve_creation_time: false })
+    }
+    pub fn copy_with(&mut self, src: &str, dst: &str, options: &CopyOptions) -> Result<(), CustomError>{
+        let (src, dst) = (&self.resolve(src)?, &self.resolve(dst)?);
+        self.record(format!("copy {} -> {}", src.display(), dst.display()), &[dst], false, |fs| fs.copy_node(src, dst, options))
+    }
+    fn copy_node(&mut self, src: &Path, dst: &Path, options: &CopyOptions) -> Result<(), CustomError>{
+        let src_parent = src.parent().ok_or(FileOrDirNameNotFound)?;
+        let dst_parent = dst.parent().ok_or(FileOrDirNameNotFound)?;
+        let mut node = self.root.get_dir(src_parent)
+            .and_then(|parent| parent.get_child(src))
+            .ok_or(FileOrDirNameNotFound)?
+            .clone();
+        if let Node::Dir(_) = node {
+            if !options.recursive {
+                return Err(CustomError::DirCopyNotRecursive);
+            }
+        }
+        let parent = self.root.get_dir_mut(dst_parent).ok_or(FileOrDirNameNotFound)?;
+        if parent.contains(dst) {
+            return Err(CustomError::DirOrFileAlreadyExists);
+        }
+        node.set_path(dst)?;
+        if !options.preserve_creation_time {
+            node.set_creation_time(timestamp_to_u64(std::time::SystemTime::now())?, true);
+        }
+        parent.add_child(node);
+        Ok(())
+    }
+    /* Runs 'mutation' and, if it succeeds, adds it to the journal with the nodes at 'paths' before and after it.
+       With 'removes' the content of the nodes removed is loaded first, so that undoing does not need the disk;
+       what cannot be read anymore does not prevent the removal. */
+    fn record<T>(&mut self, description: String, paths: &[&Path], removes: bool, mutation: impl FnOnce(&mut Self) -> Result<T, CustomError>) -> Result<T, CustomError>{
+        let mut operation = Operation::new(description);
+        let mut before = vec![];
+        for path in paths {
+            let mut node = self.root.get_node(path).cloned();
+            if let (true, Some(node)) = (removes, node.as_mut()) {
+                let _ = node.load_content();
+            }
+            before.push(node);
+        }
+        let result = mutation(self)?;
+        for (path, before) in paths.iter().zip(before) {
+            operation.add_edit(path, before, self.root.get_node(path).cloned());
+        }
+        self.journal.push(operation);
+        Ok(result)
+    }
+    // reverts the last operation in the journal, returning it, or None when there is nothing to undo
+    // the current dir goes back to the root when the operation removed it
+    pub fn undo(&mut self) -> Result<Option<&Operation>, CustomError>{
+        if self.journal.undo(&mut self.root)?.is_none() {
+            return Ok(None);
+        }
+        self.fix_cwd();
+        Ok(self.journal.get_undone().last())
     }
-    pub fn search<'a>(&'b mut self, queries: &[&'a str]) -> MatchResult<'a> where 'b: 'a, {
-        let queries: Vec<Queries> = queries
+    pub fn redo(&mut self) -> Result<Option<&Operation>, CustomError>{
+        if self.journal.redo(&mut self.root)?.is_none() {
+            return Ok(None);
+        }
+        self.fix_cwd();
+        Ok(self.journal.get_done().last())
+    }
+    pub fn get_journal(&self) -> &Journal {&self.journal}
+    // what changed from this tree to 'other', e.g. two scans of the same dir taken at different times
+    pub fn diff(&self, other: &FileSystem, options: &DiffOptions) -> Result<Diff, CustomError>{
+        diff::diff(&self.root, &other.root, options)
+    }
+    // the files of the tree holding the same content, with the space they waste
+    pub fn find_duplicates(&self) -> Duplicates{
+        duplicates::find_duplicates(&self.root)
+    }
+    // keeps a copy of the current tree, to be compared with or restored later
+    pub fn snapshot(&mut self, name: &str) -> Result<(), CustomError>{
+        if self.versions.iter().any(|version| version.get_name() == name) {
+            return Err(CustomError::SnapshotAlreadyExists(name.to_string()));
+        }
+        self.versions.push(Version::new(name, timestamp_to_u64(std::time::SystemTime::now())?, &self.root));
+        Ok(())
+    }
+    pub fn get_snapshots(&self) -> &Vec<Version> {&self.versions}
+    fn get_snapshot(&self, name: &str) -> Result<&Version, CustomError>{
+        self.versions.iter().find(|version| version.get_name() == name).ok_or_else(|| CustomError::SnapshotNotFound(name.to_string()))
+    }
+    // what changed from the snapshot to the current tree
+    pub fn diff_snapshot(&self, name: &str, options: &DiffOptions) -> Result<Diff, CustomError>{
+        diff::diff(self.get_snapshot(name)?.get_root(), &self.root, options)
+    }
+    // brings the tree back to the snapshot, which is kept; the rollback is journaled, so it can be undone too
+    pub fn rollback(&mut self, name: &str) -> Result<(), CustomError>{
+        let root = self.get_snapshot(name)?.get_root().clone();
+        let mut paths: Vec<PathBuf> = self.root.get_children().iter()
+            .chain(root.get_children())
+            .map(|child| PathBuf::from(child.get_name()))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        self.record(format!("rollback {}", name), &paths, false, |fs| {
+            fs.root = root;
+            fs.fix_cwd();
+            Ok(())
+        })
+    }
+    pub fn drop_snapshot(&mut self, name: &str) -> Result<(), CustomError>{
+        let i = self.versions.iter().position(|version| version.get_name() == name).ok_or_else(|| CustomError::SnapshotNotFound(name.to_string()))?;
+        self.versions.remove(i);
+        Ok(())
+    }
+    // goes back to the root if the current dir does not exist anymore
+    fn fix_cwd(&mut self) {
+        if self.root.get_dir(Path::new(&self.pwd())).is_none() {
+            self.cwd.clear();
+        }
+    }
+    /* Runs 'changes' as a single operation: when they fail every change made so far is reverted, current dir
+       included, otherwise they are journaled together, to be undone and redone as a whole. */
+    pub fn transaction<T>(&mut self, changes: impl FnOnce(&mut Transaction) -> Result<T, CustomError>) -> Result<T, CustomError>{
+        let checkpoint = self.journal.checkpoint();
+        let cwd = self.cwd.clone();
+        match changes(&mut Transaction::new(self)) {
+            Ok(result) => {
+                self.journal.squash(checkpoint);
+                Ok(result)
+            }
+            Err(e) => {
+                if let Err(rollback) = self.journal.rollback(checkpoint, &mut self.root) {
+                    self.fix_cwd();
+                    return Err(CustomError::RollbackFailed(Box::new(e), Box::new(rollback)));
+                }
+                self.cwd = cwd;
+                Err(e)
+            }
+        }
+    }
+    // the root must have been scanned from a disk directory, 'options' are used to read what changes in it
+    pub fn watch(&self, options: &ScanOptions) -> Result<Watcher, CustomError> {
+        Watcher::new(&self.root, options.clone())
+    }
+    pub fn walk(&self) -> Walk<'_> {
+        Walk::new(&self.root)
+    }
+    pub fn search<'a>(&'a self, queries: &[&'a str]) -> MatchResult<'a> {
+        // invalid queries are skipped, use try_search to get them reported
+        let queries: Vec<Expression> = queries
             .iter()
-            .map(|query_string| {
-                let mut query = query_string.split(':');
-                let query_type = query.next().ok_or(InvalidQuery)?;
-                let query_value = query.next().ok_or(InvalidQuery)?;
-                let enum_type_query = match query_type {
-                    "name" => Queries::Name(query_string, query_value),
-                    "content" => Queries::Content(query_string, query_value),
-                    "larger" => {
-                        let size = query_value
-                            .parse::<usize>()
-                            .map_err(|_| InvalidQuery)?;
-                        Queries::Larger(query_string, size)
-                    }
-                    "smaller" => {
-                        let size = query_value
-                            .parse::<usize>()
-                            .map_err(|_| InvalidQuery)?;
-                        Queries::Smaller(query_string, size)
-                    }
-                    "newer" => {
-                        let time = query_value
-                            .parse::<u64>()
-                            .map_err(|_| InvalidQuery)?;
-                        Queries::Newer(query_string, time)
-                    }
-                    "older" => {
-                        let time = query_value
-                            .parse::<u64>()
-                            .map_err(|_| InvalidQuery)?;
-                        Queries::Older(query_string, time)
-                    }
-                    &_ => {
-                        return Err(InvalidQuery);
-                    }
-                };
-                Ok(enum_type_query)
-            })
-            .filter_map(|x| match x {
-                Ok(q) => Some(q),
-                Err(_) => None,
-            })
+            .filter_map(|query_string| Expression::parse(query_string).ok())
             .collect();
-        self.root.search(&queries, MatchResult::default())
+        Self::search_dir(&self.root, &queries, None)
+    }
+    pub fn try_search<'a>(&'a self, queries: &[&'a str]) -> Result<MatchResult<'a>, CustomError> {
+        let queries = Self::parse_queries(queries)?;
+        Ok(Self::search_dir(&self.root, &queries, None))
+    }
+    pub fn search_from<'a>(&'a self, path: &str, queries: &[&'a str], max_depth: Option<usize>) -> Result<MatchResult<'a>, CustomError> {
+        let queries = Self::parse_queries(queries)?;
+        let dir = self.root.get_dir(&self.resolve_target(path)?).ok_or(FileOrDirNameNotFound)?;
+        Ok(Self::search_dir(dir, &queries, max_depth))
+    }
+    pub fn sync_to_disk(&self, target: &str, dry_run: bool) -> Result<Vec<SyncAction>, CustomError> {
+        let mut actions = vec![];
+        // the files still reading from the target are cached first, as syncing may delete or overwrite their source
+        let mut root = Cow::Borrowed(&self.root);
+        if let Ok(disk_dir) = std::fs::canonicalize(target) {
+            root.to_mut().load_content_below(&disk_dir)?;
+        }
+        sync_dir(&root, Path::new(target), dry_run, &mut actions)?;
+        Ok(actions)
+    }
+    pub fn save(&self, path: &str) -> Result<(), CustomError> {
+        snapshot::save(&self.root, Path::new(path))
+    }
+    pub fn load(path: &str) -> Result<FileSystem, CustomError> {
+        Ok(FileSystem{
+            root: snapshot::load(Path::new(path))?,
+            ..FileSystem::default()
+        })
+    }
+    fn parse_queries<'a>(queries: &[&'a str]) -> Result<Vec<Expression<'a>>, CustomError> {
+        queries
+            .iter()
+            .map(|query_string| Expression::parse(query_string).map_err(CustomError::InvalidQuery))
+            .collect()
+    }
+    fn search_dir<'a>(dir: &'a Dir, queries: &[Expression<'a>], max_depth: Option<usize>) -> MatchResult<'a> {
+        let mut result = MatchResult::default();
+        let mut walk = Walk::new(dir);
+        if let Some(max_depth) = max_depth {
+            walk = walk.max_depth(max_depth);
+        }
+        for (_, _, node) in walk {
+            if let Some(q) = queries.iter().find(|q| q.matches(node)) {
+                result.queries.push(q.to_str());
+                result.nodes.push(node);
+            }
+        }
+        result.queries.sort_unstable();
+        result.queries.dedup();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::file_system::dir::tests::{file, scratch_dir, tree};
+
+    fn fs_of(paths: &[&str]) -> FileSystem {
+        FileSystem { root: tree(paths), ..FileSystem::default() }
+    }
+
+    #[test]
+    fn a_failed_transaction_reverts_all_its_changes() {
+        let mut fs = fs_of(&["a/", "a/x.txt"]);
+        fs.cd("/a").unwrap();
+        let result = fs.transaction(|tx| {
+            tx.mk_dir("/b")?;
+            tx.mv("/a/x.txt", "/b/x.txt")?;
+            tx.cd("/b")?;
+            tx.rm_dir("/missing")
+        });
+        assert!(matches!(result, Err(CustomError::FileOrDirNameNotFound)));
+        assert!(fs.get_node("/b").is_none());
+        assert!(fs.get_node("/a/x.txt").is_some());
+        assert_eq!(fs.pwd(), "root/a");
+        assert!(fs.get_journal().get_done().is_empty());
+    }
+
+    #[test]
+    fn a_transaction_is_undone_and_redone_as_a_whole() {
+        let mut fs = fs_of(&[]);
+        fs.transaction(|tx| {
+            tx.mk_dir("/a")?;
+            tx.new_file("/a/x.txt", file(b"x"))
+        }).unwrap();
+        assert_eq!(fs.get_journal().get_done().len(), 1);
+        fs.undo().unwrap();
+        assert!(fs.get_node("/a").is_none());
+        fs.redo().unwrap();
+        assert!(fs.get_node("/a/x.txt").is_some());
+    }
+
+    #[test]
+    fn paths_resolve_from_the_root_or_the_current_dir() {
+        let mut fs = fs_of(&["a/", "a/b/", "c/"]);
+        fs.symlink("/a/to_c", "/c").unwrap();
+        fs.symlink("/a/b/up", "..").unwrap();
+        fs.cd("a/b").unwrap();
+        assert_eq!(fs.resolve("root/c").unwrap(), PathBuf::from("root/c"));
+        assert_eq!(fs.resolve("/c/x").unwrap(), PathBuf::from("root/c/x"));
+        assert_eq!(fs.resolve("..\\.//b/./x").unwrap(), PathBuf::from("root/a/b/x"));
+        assert_eq!(fs.resolve("../../../..").unwrap(), PathBuf::from("root"));
+        // links are followed in the middle of a path, but not at its end unless the target is asked for
+        assert_eq!(fs.resolve("up/to_c/x").unwrap(), PathBuf::from("root/c/x"));
+        assert_eq!(fs.resolve("../to_c").unwrap(), PathBuf::from("root/a/to_c"));
+        assert_eq!(fs.resolve_target("../to_c").unwrap(), PathBuf::from("root/c"));
+        fs.symlink("/loop", "/loop").unwrap();
+        assert!(matches!(fs.resolve_target("/loop"), Err(CustomError::SymlinkLoop)));
+    }
+
+    #[test]
+    fn the_current_dir_follows_moves_and_removals() {
+        let mut fs = fs_of(&["a/", "a/b/", "a/b/c/"]);
+        fs.cd("a/b/c").unwrap();
+        fs.mv("/a", "/moved").unwrap();
+        assert_eq!(fs.pwd(), "root/moved/b/c");
+        fs.mv("/moved/b/c", "/c").unwrap();
+        assert_eq!(fs.pwd(), "root/c");
+        fs.undo().unwrap();
+        assert_eq!(fs.pwd(), "root");
+        fs.cd("/moved/b").unwrap();
+        fs.rm_dir_all("/moved").unwrap();
+        assert_eq!(fs.pwd(), "root");
+        fs.mk_dir("/d").unwrap();
+        fs.cd("d").unwrap();
+        fs.rm_dir("/d").unwrap();
+        assert_eq!(fs.pwd(), "root");
+    }
+
+    #[test]
+    fn invalid_queries_are_reported_by_try_search_only() {
+        let fs = fs_of(&["a.txt", "big.txt"]);
+        assert!(fs.search(&["larger:abc"]).get_nodes().is_empty());
+        match fs.try_search(&["name:a", "name:b AND larger:abc"]) {
+            Err(CustomError::InvalidQuery(e)) => {
+                assert_eq!(e.query, "name:b AND larger:abc");
+                assert_eq!(e.position, 18);
+                assert!(matches!(e.reason, QueryErrorReason::NotANumber(ref value) if value == "abc"));
+                assert_eq!(CustomError::InvalidQuery(e).to_string(), "Invalid query 'name:b AND larger:abc' at position 18: 'abc' is not a valid number");
+            }
+            _ => panic!("the second query should be reported"),
+        }
+        let found = fs.try_search(&["name:big OR NOT (name:a AND larger:1)"]).unwrap();
+        let names: Vec<&str> = found.get_nodes().iter().map(|node| node.get_name()).collect();
+        assert_eq!(names, ["root/big.txt"]);
+    }
+
+    #[test]
+    fn mv_renames_every_descendant() {
+        let mut fs = fs_of(&["a/", "a/b/", "a/b/x.txt", "c/", "y.txt"]);
+        fs.mv("/a", "/c/renamed").unwrap();
+        assert!(fs.get_node("/a").is_none());
+        let names: Vec<&str> = fs.walk().map(|(_, _, node)| node.get_name()).collect();
+        assert!(names.contains(&"root/c/renamed/b/x.txt"));
+        assert!(names.iter().all(|name| !name.starts_with("root/a")));
+        // the content moves along, untouched
+        assert_eq!(&fs.get_file("/c/renamed/b/x.txt").unwrap().read_content().unwrap()[..], b"a/b/x.txt");
+        assert!(matches!(fs.mv("/c", "/c/renamed/b/c"), Err(CustomError::MoveIntoItself)));
+        assert!(matches!(fs.mv("/y.txt", "/c/renamed"), Err(CustomError::DirOrFileAlreadyExists)));
+        assert!(matches!(fs.mv("/missing", "/z"), Err(CustomError::FileOrDirNameNotFound)));
+        assert!(matches!(fs.mv("/y.txt", "/missing/y.txt"), Err(CustomError::FileOrDirNameNotFound)));
+        // a failed move changes nothing
+        assert!(fs.get_node("/y.txt").is_some() && fs.get_node("/c/renamed/b").is_some());
+        assert_eq!(fs.get_journal().get_done().len(), 1);
+    }
+
+    #[test]
+    fn copies_are_rebased_and_independent() {
+        let mut fs = fs_of(&["a/", "a/b/", "a/b/x.txt"]);
+        assert!(matches!(fs.copy("/a", "/c", false), Err(CustomError::DirCopyNotRecursive)));
+        fs.copy("/a", "/c", true).unwrap();
+        fs.copy_with("/a", "/d", &CopyOptions { recursive: true, preserve_creation_time: true }).unwrap();
+        assert!(matches!(fs.copy("/a", "/c", true), Err(CustomError::DirOrFileAlreadyExists)));
+        assert_eq!(fs.get_node("/c/b/x.txt").unwrap().get_name(), "root/c/b/x.txt");
+        assert!(fs.get_file("/c/b/x.txt").unwrap().get_creation_time() > 0);
+        assert_eq!(fs.get_file("/d/b/x.txt").unwrap().get_creation_time(), 0);
+        fs.get_file("/c/b/x.txt").unwrap().set_content(b"changed".to_vec());
+        assert_eq!(&fs.get_file("/a/b/x.txt").unwrap().read_content().unwrap()[..], b"a/b/x.txt");
+        assert_eq!(&fs.get_file("/d/b/x.txt").unwrap().read_content().unwrap()[..], b"a/b/x.txt");
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut fs = fs_of(&["a/", "a/x.txt"]);
+        fs.mk_dir("/b").unwrap();
+        fs.mv("/a/x.txt", "/b/y.txt").unwrap();
+        fs.rm_dir_all("/a").unwrap();
+        let history: Vec<&str> = fs.get_journal().get_done().iter().map(Operation::get_description).collect();
+        assert_eq!(history, ["mk_dir root/b", "mv root/a/x.txt -> root/b/y.txt", "rm_dir_all root/a"]);
+        assert_eq!(fs.undo().unwrap().unwrap().get_description(), "rm_dir_all root/a");
+        fs.undo().unwrap();
+        assert!(fs.get_node("/a/x.txt").is_some() && fs.get_node("/b/y.txt").is_none());
+        assert_eq!(fs.redo().unwrap().unwrap().get_description(), "mv root/a/x.txt -> root/b/y.txt");
+        assert!(fs.get_node("/b/y.txt").is_some());
+        // a new operation drops what could be redone
+        fs.new_file("/z.txt", file(b"z")).unwrap();
+        assert!(fs.redo().unwrap().is_none());
+        for _ in 0..3 {
+            fs.undo().unwrap();
+        }
+        assert!(fs.undo().unwrap().is_none());
+        assert!(fs.get_node("/b").is_none() && fs.get_node("/a/x.txt").is_some());
+    }
+
+    #[test]
+    fn removed_files_come_back_whole_even_once_gone_from_disk() {
+        let disk = scratch_dir("undo_rm");
+        fs::write(disk.join("big.txt"), vec![b'x'; 3000]).unwrap();
+        let mut fs = FileSystem::from_dir_with(disk.to_str().unwrap(), &ScanOptions { content_cap: 10, ..ScanOptions::default() }).unwrap();
+        fs.rm_file("/big.txt").unwrap();
+        fs::remove_file(disk.join("big.txt")).unwrap();
+        fs.undo().unwrap();
+        assert_eq!(fs.get_file("/big.txt").unwrap().read_content().unwrap().len(), 3000);
+        fs::remove_dir_all(disk).unwrap();
+    }
+
+    #[test]
+    fn sync_to_disk_mirrors_the_tree_and_dry_runs_touch_nothing() {
+        let disk = scratch_dir("sync_to_disk");
+        fs::write(disk.join("stale.txt"), "stale").unwrap();
+        fs::write(disk.join("a"), "a file where the tree has a dir").unwrap();
+        let fs_tree = fs_of(&["a/", "a/x.txt", "y.txt"]);
+        let target = disk.to_str().unwrap();
+        let planned: Vec<String> = fs_tree.sync_to_disk(target, true).unwrap().iter().map(ToString::to_string).collect();
+        assert!(disk.join("stale.txt").exists() && !disk.join("y.txt").exists());
+        let done: Vec<String> = fs_tree.sync_to_disk(target, false).unwrap().iter().map(ToString::to_string).collect();
+        assert_eq!(planned, done);
+        for action in [format!("remove file {}", disk.join("stale.txt").display()), format!("create dir  {}", disk.join("a").display())] {
+            assert!(done.contains(&action), "{} missing from {:?}", action, done);
+        }
+        assert!(!disk.join("stale.txt").exists());
+        assert_eq!(fs::read_to_string(disk.join("a/x.txt")).unwrap(), "a/x.txt");
+        assert_eq!(fs::read_to_string(disk.join("y.txt")).unwrap(), "y.txt");
+        // once in sync there is nothing left to do
+        assert!(fs_tree.sync_to_disk(target, false).unwrap().is_empty());
+        fs::remove_dir_all(disk).unwrap();
+    }
+
+    #[test]
+    fn the_root_cannot_be_made_in_a_transaction() {
+        let mut fs = FileSystem::new();
+        assert!(matches!(fs.transaction(|tx| tx.mk_dir("anywhere")), Err(CustomError::NoRoot)));
+        assert!(fs.get_root().get_name().is_empty());
     }
 }
@@ -0,0 +1,264 @@
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use similar::TextDiff;
+use crate::file_system::dir::{Dir, Node};
+use crate::file_system::dir::file::{CustomError, File, FileType};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DiffOptions {
+    pub text_diffs: bool,     // a unified diff for each modified Text file
+    pub context_lines: usize, // unchanged lines around each hunk of a text diff
+}
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { text_diffs: false, context_lines: 3 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Modification {
+    /* 'offset' is the first byte which differs, or the size of the shorter file when one is a prefix of the other.
+       When a source changed since its scan the change is not 'verified': the bytes past the cached ones could not
+       be compared, 'offset' is where the comparison stopped and the content may even be the same. */
+    Content { offset: u64, old_size: u64, new_size: u64, verified: bool, text_diff: Option<String> },
+    Target(PathBuf, PathBuf), // a link pointing somewhere else (old, new)
+}
+
+// the paths are the full paths in the new tree, or in the old one for what was removed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf, Modification),
+    TypeChanged(PathBuf, String, String), // (path, old type, new type), e.g. "dir" -> "text/utf-8"
+}
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "added    {}", path.display()),
+            Change::Removed(path) => write!(f, "removed  {}", path.display()),
+            Change::Modified(path, Modification::Content { offset, old_size, new_size, verified, text_diff }) => {
+                write!(f, "modified {}: differs from byte {} ({} -> {} bytes)", path.display(), offset, old_size, new_size)?;
+                if !verified {
+                    write!(f, ", unverified: changed on disk since the scan")?;
+                }
+                match text_diff {
+                    Some(text_diff) => write!(f, "\n{}", text_diff.trim_end()),
+                    None => Ok(()),
+                }
+            }
+            Change::Modified(path, Modification::Target(old, new)) => {
+                write!(f, "modified {}: target {} -> {}", path.display(), old.display(), new.display())
+            }
+            Change::TypeChanged(path, old, new) => write!(f, "retyped  {}: {} -> {}", path.display(), old, new),
+        }
+    }
+}
+impl Change {
+    pub fn get_path(&self) -> &Path {
+        match self {
+            Change::Added(path) | Change::Removed(path) | Change::Modified(path, _) | Change::TypeChanged(path, _, _) => path,
+        }
+    }
+}
+
+// the changes from one tree to another, sorted by path
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    changes: Vec<Change>,
+}
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        let count = |kind: fn(&Change) -> bool| self.changes.iter().filter(|change| kind(change)).count();
+        write!(f, "{} added, {} removed, {} modified, {} retyped",
+               count(|change| matches!(change, Change::Added(_))),
+               count(|change| matches!(change, Change::Removed(_))),
+               count(|change| matches!(change, Change::Modified(..))),
+               count(|change| matches!(change, Change::TypeChanged(..))))
+    }
+}
+impl Diff {
+    pub fn get_changes(&self) -> &Vec<Change> { &self.changes }
+    pub fn len(&self) -> usize { self.changes.len() }
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+}
+
+/* What changed from 'old' to 'new'. A dir added or removed is reported alone, without its content, and subtrees
+   shared by the two trees are skipped. Files are compared byte by byte, streaming the content which is not cached,
+   and a file whose FileType changed is reported as retyped rather than modified. */
+pub fn diff(old: &Dir, new: &Dir, options: &DiffOptions) -> Result<Diff, CustomError> {
+    let mut changes = vec![];
+    diff_dirs(old, new, options, &mut changes)?;
+    changes.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+    Ok(Diff { changes })
+}
+
+fn type_of(node: &Node) -> String {
+    match node {
+        Node::Dir(_) => "dir".to_string(),
+        Node::Symlink(_) => "symlink".to_string(),
+        Node::File(file) => file.get_filetype().to_string(),
+    }
+}
+
+fn diff_dirs(old: &Dir, new: &Dir, options: &DiffOptions, changes: &mut Vec<Change>) -> Result<(), CustomError> {
+    for old_child in old.get_children() {
+        // children are looked up by their leaf name, so the two roots do not need the same name
+        let Some(new_child) = new.get_child(Path::new(old_child.get_name())) else {
+            changes.push(Change::Removed(PathBuf::from(old_child.get_name())));
+            continue;
+        };
+        let path = PathBuf::from(new_child.get_name());
+        match (old_child, new_child) {
+            (Node::Dir(old_dir), Node::Dir(new_dir)) => {
+                if !Arc::ptr_eq(old_dir, new_dir) {
+                    diff_dirs(old_dir, new_dir, options, changes)?;
+                }
+            }
+            (Node::File(old_file), Node::File(new_file)) if old_file.get_filetype() == new_file.get_filetype() => {
+                if let Some(modification) = diff_files(old_file, new_file, options)? {
+                    changes.push(Change::Modified(path, modification));
+                }
+            }
+            (Node::Symlink(old_link), Node::Symlink(new_link)) => {
+                if old_link.get_target() != new_link.get_target() {
+                    let modification = Modification::Target(old_link.get_target().to_path_buf(), new_link.get_target().to_path_buf());
+                    changes.push(Change::Modified(path, modification));
+                }
+            }
+            _ => changes.push(Change::TypeChanged(path, type_of(old_child), type_of(new_child))),
+        }
+    }
+    for new_child in new.get_children() {
+        if !old.contains(Path::new(new_child.get_name())) {
+            changes.push(Change::Added(PathBuf::from(new_child.get_name())));
+        }
+    }
+    Ok(())
+}
+
+// None when the two files hold the same bytes
+fn diff_files(old: &File, new: &File, options: &DiffOptions) -> Result<Option<Modification>, CustomError> {
+    if old.shares_content(new) && old.get_size() == new.get_size() {
+        return Ok(None);
+    }
+    let Some((offset, verified)) = first_difference(old, new)? else { return Ok(None) };
+    // no text diff for a side whose source changed since the scan, the change itself is still reported
+    let contents = || Some((old.read_content().ok()?, new.read_content().ok()?));
+    let text_diff = match (new.get_filetype(), options.text_diffs.then(contents).flatten()) {
+        (FileType::Text(encoding), Some((old_content, new_content))) => {
+            let (old_text, new_text) = (encoding.decode(&old_content), encoding.decode(&new_content));
+            let text_diff = TextDiff::from_lines(&old_text, &new_text)
+                .unified_diff()
+                .context_radius(options.context_lines)
+                .header(old.get_name(), new.get_name())
+                .to_string();
+            Some(text_diff)
+        }
+        _ => None,
+    };
+    Ok(Some(Modification::Content { offset, old_size: old.get_size(), new_size: new.get_size(), verified, text_diff }))
+}
+
+/* The cached prefixes are compared first, as they hold what each scan saw even when the disk changed since. The
+   rest is streamed from the sources, which fail when changed since the scan: two scans of the same dir would
+   otherwise both read the current bytes. The files are then reported as different from the end of the prefixes,
+   unverified. Returns (offset, verified). */
+fn first_difference(old: &File, new: &File) -> Result<Option<(u64, bool)>, CustomError> {
+    let (old_cached, new_cached) = (old.get_content(), new.get_content());
+    let len = old_cached.len().min(new_cached.len());
+    if let Some(i) = old_cached[..len].iter().zip(&new_cached[..len]).position(|(a, b)| a != b) {
+        return Ok(Some((i as u64, true)));
+    }
+    let sizes_differ = old.get_size() != new.get_size();
+    if len as u64 == old.get_size().min(new.get_size()) || (!sizes_differ && old.shares_source(new)) {
+        return Ok(sizes_differ.then_some((len as u64, true)));
+    }
+    match stream_difference(old, new) {
+        Ok(offset) => Ok(offset.map(|offset| (offset, true))),
+        Err(CustomError::SourceChanged(_)) => Ok(Some((len as u64, false))),
+        Err(e) => Err(e),
+    }
+}
+
+// compares the two contents chunk by chunk, so that neither is ever held whole in memory
+fn stream_difference(old: &File, new: &File) -> Result<Option<u64>, CustomError> {
+    let (mut old_reader, mut new_reader) = (BufReader::new(old.reader()?), BufReader::new(new.reader()?));
+    let mut offset = 0;
+    loop {
+        let (old_chunk, new_chunk) = (old_reader.fill_buf()?, new_reader.fill_buf()?);
+        if old_chunk.is_empty() || new_chunk.is_empty() {
+            return Ok((old_chunk.is_empty() != new_chunk.is_empty()).then_some(offset));
+        }
+        let len = old_chunk.len().min(new_chunk.len());
+        if let Some(i) = old_chunk[..len].iter().zip(&new_chunk[..len]).position(|(a, b)| a != b) {
+            return Ok(Some(offset + i as u64));
+        }
+        old_reader.consume(len);
+        new_reader.consume(len);
+        offset += len as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+    use crate::file_system::dir::ScanOptions;
+    use crate::file_system::dir::tests::{file, scratch_dir, tree};
+
+    #[test]
+    fn reports_every_kind_of_change_sorted_by_path() {
+        let old = tree(&["a.txt", "b/", "b/c.txt", "gone.txt", "kind"]);
+        let mut new = tree(&["a.txt", "b/", "new.txt", "kind/"]);
+        new.new_file(Path::new("root/b/c.txt"), &file(b"b/c.tXt")).unwrap();
+        let changes = diff(&old, &new, &DiffOptions::default()).unwrap();
+        let summary: Vec<String> = changes.get_changes().iter().map(|change| change.to_string()).collect();
+        assert_eq!(summary, [
+            "modified root/b/c.txt: differs from byte 5 (7 -> 7 bytes)",
+            "removed  root/gone.txt",
+            "retyped  root/kind: text/utf-8 -> dir",
+            "added    root/new.txt",
+        ]);
+    }
+
+    #[test]
+    fn text_diffs_are_unified() {
+        let mut old = tree(&[]);
+        old.new_file(Path::new("root/a.txt"), &file(b"one\ntwo\n")).unwrap();
+        let mut new = tree(&[]);
+        new.new_file(Path::new("root/a.txt"), &file(b"one\n2\n")).unwrap();
+        let options = DiffOptions { text_diffs: true, ..DiffOptions::default() };
+        match &diff(&old, &new, &options).unwrap().get_changes()[..] {
+            [Change::Modified(_, Modification::Content { offset: 4, verified: true, text_diff: Some(text_diff), .. })] => {
+                assert!(text_diff.contains("-two\n+2\n"));
+            }
+            changes => panic!("unexpected {:?}", changes),
+        }
+    }
+
+    #[test]
+    fn a_change_past_the_cache_of_two_scans_is_reported_unverified() {
+        let dir = scratch_dir("diff_scans");
+        let name = dir.to_str().unwrap();
+        let options = ScanOptions { content_cap: 1000, ..ScanOptions::default() };
+        let mut content = vec![b'a'; 3000];
+        fs::write(dir.join("big.txt"), &content).unwrap();
+        let before = Dir::scan(name, &options).unwrap();
+        content[2500] = b'b';
+        fs::write(dir.join("big.txt"), &content).unwrap();
+        let after = Dir::scan(name, &options).unwrap();
+        let changes = diff(&before, &after, &DiffOptions::default()).unwrap();
+        assert!(matches!(changes.get_changes()[..], [Change::Modified(_, Modification::Content { offset: 1000, verified: false, .. })]));
+        // two scans of the same state are equal without reading the disk, even once it changed again
+        let again = Dir::scan(name, &options).unwrap();
+        fs::write(dir.join("big.txt"), b"changed").unwrap();
+        assert!(diff(&after, &again, &DiffOptions::default()).unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
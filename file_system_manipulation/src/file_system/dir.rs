@@ -1,64 +1,140 @@
 pub mod file;
+pub mod symlink;
 
-use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::Path;
-use crate::file_system::dir::file::{CustomError, File, timestamp_to_u64};
-use std::time::UNIX_EPOCH;
-use crate::file_system::{MatchResult, Queries};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::file_system::dir::file::{CustomError, File, NodeMetadata, creation_time_of, timestamp_to_u64, DEFAULT_CONTENT_CAP};
+use crate::file_system::dir::symlink::Symlink;
 
+#[derive(Clone)]
 pub enum Node {
     File(File),
-    Dir(RefCell<Dir>),
+    Dir(Arc<Dir>), // shared with the copies of the tree until one of them changes it
+    Symlink(Symlink),
 }
 impl Display for Node{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self{
-            Node::Dir(dir) => write!(f, "{}", *dir.borrow()),
-            Node::File(file) => write!(f, "{}", file)
+            Node::Dir(dir) => write!(f, "{}", dir),
+            Node::File(file) => write!(f, "{}", file),
+            Node::Symlink(link) => write!(f, "{}", link)
         }
     }
 }
 impl PartialEq<Path> for Node {
     fn eq(&self, other: &Path) -> bool {
         match self {
-            Node::Dir(dir) => Path::new(&dir.borrow().name) == other,
-            Node::File(file) => Path::new(file.get_name()) == other
+            Node::Dir(dir) => Path::new(&dir.name) == other,
+            Node::File(file) => Path::new(file.get_name()) == other,
+            Node::Symlink(link) => Path::new(link.get_name()) == other
         }
     }
 }
-impl<'b> Node{
-    pub fn search<'a>(&'b mut self, queries: &[Queries<'a>], mut result: MatchResult<'a>) -> MatchResult<'a> where 'b: 'a, {
+impl Node{
+    pub fn get_name(&self) -> &str {
         match self {
-            Self::File(_) => {
-                if let Some(q) = queries.iter().find(|q| q.matches(self)) {
-                    result.queries.push(q.to_str());
-                    result.nodes.push(self);
+            Node::Dir(dir) => dir.get_name(),
+            Node::File(file) => file.get_name(),
+            Node::Symlink(link) => link.get_name()
+        }
+    }
+    // renames the node to 'path', rebasing the full path stored in every descendant
+    pub fn set_path(&mut self, path: &Path) -> Result<(), CustomError> {
+        let name = path.to_str().ok_or(CustomError::FileOrDirNameNotFound)?.to_string();
+        match self {
+            Node::File(file) => file.set_name(name),
+            Node::Symlink(link) => link.set_name(name),
+            Node::Dir(dir) => {
+                let dir = Arc::make_mut(dir);
+                dir.name = name;
+                for child in dir.children.iter_mut() {
+                    let leaf = Path::new(child.get_name()).file_name().ok_or(CustomError::FileOrDirNameNotFound)?.to_owned();
+                    child.set_path(&path.join(leaf))?;
                 }
-            },
-            Self::Dir(dir) => {
-                if let Some(q) = queries.iter().find(|q| q.matches(self)) {
-                    result.queries.push(q.to_str());
-                    result.nodes.push(self);
+            }
+        }
+        Ok(())
+    }
+    // reads from the disk the content which is not cached yet, in every file below this node
+    pub fn load_content(&mut self) -> Result<(), CustomError> {
+        match self {
+            Node::File(file) => file.load_content(),
+            Node::Dir(dir) => Arc::make_mut(dir).children.iter_mut().try_for_each(Node::load_content),
+            Node::Symlink(_) => Ok(())
+        }
+    }
+    pub fn set_creation_time(&mut self, creation_time: u64, recursive: bool) {
+        match self {
+            Node::File(file) => file.set_creation_time(creation_time),
+            Node::Symlink(link) => link.set_creation_time(creation_time),
+            Node::Dir(dir) => {
+                let dir = Arc::make_mut(dir);
+                dir.creation_time = creation_time;
+                if recursive {
+                    dir.children.iter_mut().for_each(|child| child.set_creation_time(creation_time, true));
                 }
-                //result = dir.search(queries, result)
             }
         }
-        result
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, PartialEq)]
+pub struct RemoveSummary {
+    pub files: usize,
+    pub dirs: usize,
+}
+impl Display for RemoveSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "removed {} files and {} dirs", self.files, self.dirs)
+    }
+}
+impl RemoveSummary {
+    pub fn of(node: &Node) -> RemoveSummary {
+        match node {
+            Node::File(_) | Node::Symlink(_) => RemoveSummary { files: 1, dirs: 0 }, // a link counts as a file
+            Node::Dir(dir) => dir.children.iter().map(RemoveSummary::of).fold(
+                RemoveSummary { files: 0, dirs: 1 },
+                |total, child| RemoveSummary { files: total.files + child.files, dirs: total.dirs + child.dirs },
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ScanOptions {
+    pub content_cap: usize, // max bytes of each file kept in memory, the rest is read from disk on demand
+    pub follow_symlinks: bool, // scan what the links point to, otherwise they are kept as Symlink nodes
+}
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions{
+            content_cap: DEFAULT_CONTENT_CAP,
+            follow_symlinks: false
+        }
+    }
+}
+
+fn leaf_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()
+}
+
+/* Children are kept in a Vec, to iterate them, and indexed by their leaf name, so that a full path is resolved
+   with one O(1) lookup per component instead of scanning every sibling. */
+#[derive(Default, Clone)]
 pub struct Dir {
     name: String,
     creation_time: u64,
+    metadata: NodeMetadata,
     children: Vec<Node>,
+    index: HashMap<String, usize>, // leaf name -> position in children
 }
 impl Display for Dir{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut res: std::fmt::Result;
-        res = write!(f, "Dir: name={}, creation_time={}\n", self.name, self.creation_time);
+        res = writeln!(f, "Dir: name={}, creation_time={}", self.name, self.creation_time);
         for child in self.children.iter() {
             res = write!(f, "{}", child);
         }
@@ -71,27 +147,48 @@ impl PartialEq<Path> for Dir {
         Path::new(&self.name) == other
     }
 }
-impl<'b> Dir{
+impl Dir{
     pub fn new(path: &str) -> Result<Dir, CustomError>{
-        let mut dir = Dir{
-            name: path.to_string(),
-            creation_time: 0,
-            children: vec![]
-        };
+        Dir::scan(path, &ScanOptions::default())
+    }
+    pub fn scan(path: &str, options: &ScanOptions) -> Result<Dir, CustomError>{
+        Dir::scan_with_skipped(path, options, &mut vec![])
+    }
+    // what is neither a dir, a file nor a link, e.g. a socket or a fifo, is left out and its path pushed to 'skipped'
+    pub fn scan_with_skipped(path: &str, options: &ScanOptions, skipped: &mut Vec<PathBuf>) -> Result<Dir, CustomError>{
+        Dir::scan_below(path, options, &mut vec![], skipped)
+    }
+    /* 'ancestors' are the canonical paths of the dirs being scanned, the scanned root first: when following links,
+       a link to one of them would be scanned forever, so it is kept as a Symlink node. Dangling links are kept as
+       Symlink nodes too. */
+    fn scan_below(path: &str, options: &ScanOptions, ancestors: &mut Vec<PathBuf>, skipped: &mut Vec<PathBuf>) -> Result<Dir, CustomError>{
         let file_info = fs::metadata(path)?;
-        dir.creation_time = file_info.created()?.duration_since(UNIX_EPOCH)?.as_secs();
+        let mut dir = Dir::new_from_dir(Path::new(path), creation_time_of(&file_info))?;
+        dir.metadata = NodeMetadata::from_fs(&file_info);
+        ancestors.push(fs::canonicalize(path)?);
         let dir_children = fs::read_dir(path)?;
         for child in dir_children {
             let child = child?;
-            let child_metadata = child.metadata()?;
+            let mut child_metadata = child.metadata()?;
+            if child_metadata.is_symlink() {
+                match fs::metadata(child.path()) {
+                    Ok(target_metadata) if options.follow_symlinks
+                        && !(target_metadata.is_dir() && ancestors.contains(&fs::canonicalize(child.path())?)) => child_metadata = target_metadata,
+                    _ => {
+                        dir.add_child(Node::Symlink(Symlink::read(&child.path(), &ancestors[0])?));
+                        continue;
+                    }
+                }
+            }
             if child_metadata.is_dir() {
-                dir.children.push(Node::Dir(RefCell::new(Dir::new(child.path().to_str().ok_or(CustomError::FileOrDirNameNotFound)?)?)));
+                dir.add_child(Node::Dir(Arc::new(Dir::scan_below(child.path().to_str().ok_or(CustomError::FileOrDirNameNotFound)?, options, ancestors, skipped)?)));
             } else if child_metadata.is_file() {
-                dir.children.push(Node::File(File::new( child.path().to_str().ok_or(CustomError::FileOrDirNameNotFound)?.to_string(), child_metadata)?));
+                dir.add_child(Node::File(File::new( child.path().to_str().ok_or(CustomError::FileOrDirNameNotFound)?.to_string(), child_metadata, options.content_cap)?));
             } else {
-                println!("Content not recognized: {:?}, type is {:?}", child.path(), child_metadata.file_type());
+                skipped.push(child.path());
             }
         }
+        ancestors.pop();
         Ok(dir)
     }
     pub fn new_from_dir(path: &Path, creation_time: u64) -> Result<Dir, CustomError>{
@@ -99,126 +196,191 @@ impl<'b> Dir{
         Ok(Dir{
             name,
             creation_time,
-            children: vec![]
+            metadata: NodeMetadata::new(creation_time),
+            children: vec![],
+            index: HashMap::new()
         })
     }
-    pub fn is_empty(&self) -> bool {self.children.len() == 0}
-    pub fn mk_dir(&mut self, path: &Path) -> Result<(), CustomError>{
-        if path.parent().unwrap().to_str().ok_or(CustomError::FileOrDirNameNotFound)? == &self.name{
-            if self.children.iter().any(|child| child == path) {
-                return Err(CustomError::DirOrFileAlreadyExists);
-            }
-            self.children.push( Node::Dir(RefCell::new(Dir::new_from_dir(path, timestamp_to_u64(std::time::SystemTime::now())?)?)));
-        }else{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(dir) => dir.mk_dir(&path)?,
-                    Node::File(_file) => continue
-                }
+    pub fn is_empty(&self) -> bool {self.children.is_empty()}
+    // a child with the same leaf name is replaced
+    pub fn add_child(&mut self, child: Node) {
+        let leaf = leaf_name(Path::new(child.get_name())).unwrap_or_default().to_string();
+        match self.index.get(&leaf) {
+            Some(&i) => self.children[i] = child,
+            None => {
+                self.index.insert(leaf, self.children.len());
+                self.children.push(child);
             }
         }
-        Ok(())
     }
-    pub fn rm_dir(&mut self, path: &Path) -> Result<(), CustomError>{
-        if path.parent().unwrap().to_str().ok_or(CustomError::FileOrDirNameNotFound)? == &self.name{
-            let mut index_to_remove: usize = 0;
-            if !self.children
-                .iter()
-                .enumerate()
-                .any(|(i, child)|
-                    match child {
-                        Node::Dir(dir) =>  {
-                            let res = &dir.name == path.to_str().unwrap() && dir.is_empty();
-                            if res {index_to_remove = i;}
-                            res
-                        },
-                        Node::File(_file) => false
-                    }
-                ) { return Err(CustomError::FileOrDirNameNotFound); }
-            self.children.remove(index_to_remove);
-        }else{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(dir) => dir.rm_dir(&path)?,
-                    Node::File(_file) => continue
-                }
-            }
+    // the path must be a child of this dir
+    pub fn get_child(&self, path: &Path) -> Option<&Node> {
+        self.index.get(leaf_name(path)?).map(|&i| &self.children[i])
+    }
+    fn get_child_mut(&mut self, path: &Path) -> Option<&mut Node> {
+        let i = *self.index.get(leaf_name(path)?)?;
+        Some(&mut self.children[i])
+    }
+    pub fn contains(&self, path: &Path) -> bool {
+        self.get_child(path).is_some()
+    }
+    pub fn take_child(&mut self, path: &Path) -> Option<Node> {
+        let i = self.index.remove(leaf_name(path)?)?;
+        let child = self.children.swap_remove(i);
+        if let Some(moved) = self.children.get(i) {
+            self.index.insert(leaf_name(Path::new(moved.get_name())).unwrap_or_default().to_string(), i);
+        }
+        Some(child)
+    }
+    pub fn get_node(&self, path: &Path) -> Option<&Node>{
+        self.get_dir(path.parent()?)?.get_child(path)
+    }
+    pub fn get_dir(&self, path: &Path) -> Option<&Dir>{
+        let mut dir = self;
+        for component in path.strip_prefix(&self.name).ok()?.components() {
+            dir = match dir.get_child(Path::new(component.as_os_str()))? {
+                Node::Dir(child) => child,
+                _ => return None
+            };
+        }
+        Some(dir)
+    }
+    pub fn get_dir_mut(&mut self, path: &Path) -> Option<&mut Dir>{
+        let relative = path.strip_prefix(&self.name).ok()?;
+        let mut dir = self;
+        for component in relative.components() {
+            dir = match dir.get_child_mut(Path::new(component.as_os_str()))? {
+                Node::Dir(child) => Arc::make_mut(child), // copied if shared with another tree
+                _ => return None
+            };
         }
+        Some(dir)
+    }
+    fn get_parent_mut(&mut self, path: &Path) -> Result<&mut Dir, CustomError>{
+        path.parent()
+            .and_then(|parent| self.get_dir_mut(parent))
+            .ok_or(CustomError::FileOrDirNameNotFound)
+    }
+    pub fn mk_dir(&mut self, path: &Path) -> Result<(), CustomError>{
+        let parent = self.get_parent_mut(path)?;
+        if parent.contains(path) {
+            return Err(CustomError::DirOrFileAlreadyExists);
+        }
+        parent.add_child(Node::Dir(Arc::new(Dir::new_from_dir(path, timestamp_to_u64(std::time::SystemTime::now())?)?)));
         Ok(())
     }
+    pub fn rm_dir(&mut self, path: &Path, recursive: bool) -> Result<RemoveSummary, CustomError>{
+        let parent = self.get_parent_mut(path)?;
+        match parent.get_child(path) {
+            Some(Node::Dir(dir)) if !recursive && !dir.is_empty() => Err(CustomError::DirNotEmpty),
+            Some(Node::Dir(_)) => Ok(RemoveSummary::of(&parent.take_child(path).ok_or(CustomError::FileOrDirNameNotFound)?)),
+            _ => Err(CustomError::FileOrDirNameNotFound)
+        }
+    }
     pub fn new_file(&mut self, path: &Path, file: &File) -> Result<(), CustomError>{
-        if path.parent().unwrap().to_str().ok_or(CustomError::FileOrDirNameNotFound)? == &self.name{
-            if self.children.iter().any(|child| child == path) {
-                return Err(CustomError::DirOrFileAlreadyExists);
-            }
-            self.children.push( Node::File(File::new_from_file(path, file)?));
-        }else{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(dir) => dir.new_file(path, file)?,
-                    Node::File(_file) => continue
-                }
-            }
+        let parent = self.get_parent_mut(path)?;
+        if parent.contains(path) {
+            return Err(CustomError::DirOrFileAlreadyExists);
+        }
+        parent.add_child(Node::File(File::new_from_file(path, file)?));
+        Ok(())
+    }
+    pub fn new_symlink(&mut self, path: &Path, target: &Path) -> Result<(), CustomError>{
+        let parent = self.get_parent_mut(path)?;
+        if parent.contains(path) {
+            return Err(CustomError::DirOrFileAlreadyExists);
         }
+        parent.add_child(Node::Symlink(Symlink::new(path, target, timestamp_to_u64(std::time::SystemTime::now())?)?));
         Ok(())
     }
+    // removes a file or a link, never what the link points to
     pub fn rm_file(&mut self, path: &Path) -> Result<(), CustomError>{
-        if path.parent().unwrap().to_str().ok_or(CustomError::FileOrDirNameNotFound)? == &self.name{
-            if self.is_empty() {
-                return Err(CustomError::FileNotFound);
-            }
-            let mut index_to_remove: usize = 0;
-            if !self.children
-                .iter()
-                .enumerate()
-                .any(|(i, child)|
-                    match child {
-                        Node::Dir(_dir) =>  false,
-                        Node::File(file) => {
-                            let res = file.get_name() == path.to_str().unwrap();
-                            if res {index_to_remove = i;}
-                            res
-                        }
+        let parent = self.get_parent_mut(path)?;
+        if parent.is_empty() {
+            return Err(CustomError::FileNotFound);
+        }
+        match parent.get_child(path) {
+            Some(Node::File(_) | Node::Symlink(_)) => { parent.take_child(path); Ok(()) },
+            _ => Err(CustomError::FileOrDirNameNotFound)
+        }
+    }
+    /* Caches the content of the files below this dir which is still read from inside the disk dir 'disk_dir'
+       (canonical), so that it survives the disk dir being changed. A source which cannot be found is loaded too,
+       so that the error is reported now. */
+    pub fn load_content_below(&mut self, disk_dir: &Path) -> Result<(), CustomError>{
+        for child in &mut self.children {
+            match child {
+                Node::File(file) => {
+                    let below = file.get_source().map(|source| fs::canonicalize(source).map_or(true, |source| source.starts_with(disk_dir)));
+                    if below == Some(true) {
+                        file.load_content()?;
                     }
-                ) { return Err(CustomError::FileOrDirNameNotFound); }
-            self.children.remove(index_to_remove);
-        }else{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(dir) => dir.rm_file(path)?,
-                    Node::File(_file) => continue
                 }
+                Node::Dir(dir) => Arc::make_mut(dir).load_content_below(disk_dir)?,
+                Node::Symlink(_) => {}
             }
         }
         Ok(())
     }
     pub fn get_file(&mut self, path: &Path) -> Option<&mut File>{
-        if path.parent().unwrap().to_str().ok_or(CustomError::FileOrDirNameNotFound).ok()? == &self.name{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(_dir) =>  continue,
-                    Node::File(file) => if file.get_name() == path.to_str().unwrap() { return Some(file); }
-                }
-            }
-        }else{
-            for child in self.children.iter_mut() {
-                match child {
-                    Node::Dir(dir) =>  return dir.get_file(path),
-                    Node::File(_file) => continue
-                }
-            }
+        match self.get_parent_mut(path).ok()?.get_child_mut(path)? {
+            Node::File(file) => Some(file),
+            _ => None
         }
-        None
-    }
-    pub fn search<'a>(&'b mut self, queries: &[Queries<'a>], mut result: MatchResult<'a>) -> MatchResult<'a> where 'b: 'a, {
-        for child in self.children.iter_mut() {
-            result = child.search(queries, result)
-        }
-        result.queries.sort_unstable();
-        result.queries.dedup();
-        result
     }
 
     pub fn get_name(&self) -> &str { &self.name }
     pub fn get_creation_time(&self) -> u64 { self.creation_time }
+    pub fn get_metadata(&self) -> &NodeMetadata { &self.metadata }
+    pub fn set_metadata(&mut self, metadata: NodeMetadata) { self.metadata = metadata; }
+    pub fn get_children(&self) -> &Vec<Node> { &self.children }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    // a file which only exists in memory
+    pub(crate) fn file(content: &[u8]) -> File {
+        let mut file = File::default();
+        file.set_content(content.to_vec());
+        file
+    }
+
+    // a tree named "root": paths ending with '/' are dirs, the others files holding their own path
+    pub(crate) fn tree(paths: &[&str]) -> Dir {
+        let mut root = Dir::new_from_dir(Path::new("root"), 0).unwrap();
+        for path in paths {
+            let full = Path::new("root").join(path.trim_end_matches('/'));
+            if path.ends_with('/') {
+                root.mk_dir(&full).unwrap();
+            } else {
+                root.new_file(&full, &file(path.as_bytes())).unwrap();
+            }
+        }
+        root
+    }
+
+    // an empty disk dir of its own for each test
+    pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fs_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rm_dir_refuses_content_unless_recursive_and_sums_up_what_it_removed() {
+        let mut root = tree(&["a/", "a/b/", "a/b/x.txt", "a/y.txt", "empty/"]);
+        root.new_symlink(Path::new("root/a/b/link"), Path::new("/a/y.txt")).unwrap();
+        assert!(matches!(root.rm_dir(Path::new("root/a"), false), Err(CustomError::DirNotEmpty)));
+        assert!(matches!(root.rm_dir(Path::new("root/a/y.txt"), true), Err(CustomError::FileOrDirNameNotFound)));
+        assert!(matches!(root.rm_dir(Path::new("root/missing"), true), Err(CustomError::FileOrDirNameNotFound)));
+        assert_eq!(root.rm_dir(Path::new("root/empty"), false).unwrap(), RemoveSummary { files: 0, dirs: 1 });
+        let summary = root.rm_dir(Path::new("root/a"), true).unwrap();
+        assert_eq!(summary, RemoveSummary { files: 3, dirs: 2 });
+        assert_eq!(summary.to_string(), "removed 3 files and 2 dirs");
+        assert!(root.is_empty());
+    }
 }
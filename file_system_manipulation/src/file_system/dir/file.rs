@@ -2,16 +2,31 @@ use std::fmt::{Display, Formatter};
 use std::fs;
 use std::fs::OpenOptions;
 use std::io::{BufReader, Read};
-use std::path::Path;
-use std::time::UNIX_EPOCH;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use crate::file_system::query::QueryError;
 
 #[derive(Debug)]
 pub enum CustomError {
     FileOrDirNameNotFound,
     DirOrFileAlreadyExists,
     FileNotFound,
-    InvalidQuery,
+    DirNotEmpty,
+    MoveIntoItself,
+    DirCopyNotRecursive,
+    SymlinkLoop,
+    InvalidQuery(QueryError),
+    InvalidSnapshot(String),
+    SnapshotNotFound(String),
+    SnapshotAlreadyExists(String),
+    SourceChanged(PathBuf),
+    NoRoot,
+    RollbackFailed(Box<CustomError>, Box<CustomError>), // (why the transaction failed, why it was not rolled back)
     IoError(std::io::Error),
+    WatchError(notify::Error),
     SystemTimeError(std::time::SystemTimeError),
 }
 impl Display for CustomError {
@@ -20,8 +35,19 @@ impl Display for CustomError {
             CustomError::FileOrDirNameNotFound => write!(f, "Content name not found"),
             CustomError::DirOrFileAlreadyExists => write!(f, "Content already exists. Cannot replicate it."),
             CustomError::FileNotFound => write!(f, "Directory empty. Cannot remove file which does not exist"),
-            CustomError::InvalidQuery => write!(f, "Invalid query. Cannot understand which query to select"),
+            CustomError::DirNotEmpty => write!(f, "Directory not empty. Remove it recursively to delete its content too"),
+            CustomError::MoveIntoItself => write!(f, "Cannot move a directory inside itself"),
+            CustomError::DirCopyNotRecursive => write!(f, "Cannot copy a directory without copying its content recursively"),
+            CustomError::SymlinkLoop => write!(f, "Too many levels of symbolic links"),
+            CustomError::InvalidQuery(e) => write!(f, "Invalid query {}", e),
+            CustomError::InvalidSnapshot(reason) => write!(f, "Invalid snapshot: {}", reason),
+            CustomError::SnapshotNotFound(name) => write!(f, "Snapshot '{}' not found", name),
+            CustomError::SnapshotAlreadyExists(name) => write!(f, "Snapshot '{}' already exists", name),
+            CustomError::SourceChanged(path) => write!(f, "'{}' changed on disk since it was scanned, its content is not cached", path.display()),
+            CustomError::NoRoot => write!(f, "The FileSystem has no root yet, make it with mk_dir outside of a transaction"),
+            CustomError::RollbackFailed(e, rollback) => write!(f, "{}, and the changes could not be rolled back: {}", e, rollback),
             CustomError::IoError(e) => write!(f, "I/O error: {}", e),
+            CustomError::WatchError(e) => write!(f, "Watch error: {}", e),
             CustomError::SystemTimeError(e) => write!(f, "System time error: {}", e),
         }
     }
@@ -32,6 +58,11 @@ impl From<std::io::Error> for CustomError {
         CustomError::IoError(e)
     }
 }
+impl From<notify::Error> for CustomError {
+    fn from(e: notify::Error) -> Self {
+        CustomError::WatchError(e)
+    }
+}
 impl From<std::time::SystemTimeError> for CustomError {
     fn from(e: std::time::SystemTimeError) -> Self {
         CustomError::SystemTimeError(e)
@@ -42,27 +73,250 @@ pub fn timestamp_to_u64(time: std::time::SystemTime) -> Result<u64, std::time::S
     Ok(time.duration_since(UNIX_EPOCH)?.as_secs())
 }
 
-#[derive(PartialEq)]
+// not every filesystem records when a file was created, the last modification is the closest thing to it
+pub fn creation_time_of(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .ok()
+        .and_then(|time| timestamp_to_u64(time).ok())
+        .unwrap_or(0)
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct NodeMetadata {
+    pub modified_time: u64,
+    pub accessed_time: u64,
+    pub disk_size: u64, // bytes allocated on disk, not the length of the content
+    pub mode: Option<u32>, // mode, uid, gid and inode are only known on unix-like systems
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub inode: Option<u64>,
+}
+impl NodeMetadata {
+    // for nodes which only exist in memory
+    pub fn new(time: u64) -> NodeMetadata {
+        NodeMetadata {
+            modified_time: time,
+            accessed_time: time,
+            ..NodeMetadata::default()
+        }
+    }
+    pub fn from_fs(metadata: &fs::Metadata) -> NodeMetadata {
+        let seconds = |time: std::io::Result<std::time::SystemTime>| time.ok().and_then(|time| timestamp_to_u64(time).ok()).unwrap_or(0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            NodeMetadata {
+                modified_time: seconds(metadata.modified()),
+                accessed_time: seconds(metadata.accessed()),
+                disk_size: metadata.blocks() * 512,
+                mode: Some(metadata.mode()),
+                uid: Some(metadata.uid()),
+                gid: Some(metadata.gid()),
+                inode: Some(metadata.ino()),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            NodeMetadata {
+                modified_time: seconds(metadata.modified()),
+                accessed_time: seconds(metadata.accessed()),
+                disk_size: metadata.len(),
+                ..NodeMetadata::default()
+            }
+        }
+    }
+}
+
+pub const SNIFF_SIZE: usize = 1024; // bytes looked at to detect the FileType
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+impl TextEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf8Bom => "utf-8-bom",
+            TextEncoding::Utf16Le => "utf-16le",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Latin1 => "latin-1",
+        }
+    }
+    // the bytes 'text' is made of in a file with this encoding, None when it has chars Latin-1 cannot hold
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            TextEncoding::Utf16Le => Some(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()),
+            TextEncoding::Utf16Be => Some(text.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect()),
+            TextEncoding::Latin1 => text.chars().map(|c| u8::try_from(c).ok()).collect(),
+            TextEncoding::Utf8 | TextEncoding::Utf8Bom => Some(text.as_bytes().to_vec()),
+        }
+    }
+    // the text in the bytes of a file with this encoding, without byte order mark; invalid sequences become U+FFFD
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let utf16 = |unit: fn([u8; 2]) -> u16| {
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units).trim_start_matches('\u{feff}').to_string()
+        };
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            TextEncoding::Utf8Bom => String::from_utf8_lossy(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes)).into_owned(),
+            TextEncoding::Utf16Le => utf16(u16::from_le_bytes),
+            TextEncoding::Utf16Be => utf16(u16::from_be_bytes),
+            TextEncoding::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BinaryKind {
+    Png,
+    Jpeg,
+    Gif,
+    Pdf,
+    Zip,
+    Gzip,
+    Elf,
+    Exe,
+    Unknown,
+}
+impl BinaryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BinaryKind::Png => "png",
+            BinaryKind::Jpeg => "jpeg",
+            BinaryKind::Gif => "gif",
+            BinaryKind::Pdf => "pdf",
+            BinaryKind::Zip => "zip",
+            BinaryKind::Gzip => "gzip",
+            BinaryKind::Elf => "elf",
+            BinaryKind::Exe => "exe",
+            BinaryKind::Unknown => "unknown",
+        }
+    }
+}
+
+const MAGIC_NUMBERS: [(&[u8], BinaryKind); 9] = [
+    (b"\x89PNG\r\n\x1a\n", BinaryKind::Png),
+    (b"\xff\xd8\xff", BinaryKind::Jpeg),
+    (b"GIF87a", BinaryKind::Gif),
+    (b"GIF89a", BinaryKind::Gif),
+    (b"%PDF-", BinaryKind::Pdf),
+    (b"PK\x03\x04", BinaryKind::Zip),
+    (b"PK\x05\x06", BinaryKind::Zip),
+    (b"\x1f\x8b", BinaryKind::Gzip),
+    (b"\x7fELF", BinaryKind::Elf),
+];
+
+// "MZ" alone starts plenty of text, an executable also has the offset of a "PE\0\0" header at 0x3c
+fn is_exe(sample: &[u8]) -> bool {
+    let Some(offset) = sample.get(0x3c..0x40) else { return false };
+    let offset = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+    sample.starts_with(b"MZ") && sample.get(offset..offset.saturating_add(4)) == Some(b"PE\0\0")
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum FileType {
-    Text, Binary
+    Text(TextEncoding),
+    Binary(BinaryKind),
 }
 impl Default for FileType {
-    fn default() -> Self { FileType::Text }
+    fn default() -> Self { FileType::Text(TextEncoding::Utf8) }
+}
+impl Display for FileType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileType::Text(encoding) => write!(f, "text/{}", encoding.name()),
+            FileType::Binary(kind) => write!(f, "binary/{}", kind.name()),
+        }
+    }
+}
+impl FileType {
+    pub const NAMES: [&'static str; 16] = [
+        "text", "utf-8", "utf-8-bom", "utf-16le", "utf-16be", "latin-1",
+        "binary", "png", "jpeg", "gif", "pdf", "zip", "gzip", "elf", "exe", "unknown",
+    ];
+    /* Guesses the type from the first bytes of a file ('truncated' when there are more):
+        1) a magic number or PE header    -> Binary of that kind
+        2) a byte order mark              -> Text in that encoding
+        3) NUL bytes                      -> Binary
+        4) valid UTF-8                    -> Text
+        5) few control characters         -> Text in Latin-1, Binary otherwise */
+    pub fn detect(sample: &[u8], truncated: bool) -> FileType {
+        if let Some((_, kind)) = MAGIC_NUMBERS.iter().find(|(magic, _)| sample.starts_with(magic)) {
+            return FileType::Binary(*kind);
+        }
+        if is_exe(sample) {
+            return FileType::Binary(BinaryKind::Exe);
+        }
+        if sample.starts_with(b"\xef\xbb\xbf") {
+            return FileType::Text(TextEncoding::Utf8Bom);
+        }
+        if sample.starts_with(b"\xff\xfe") {
+            return FileType::Text(TextEncoding::Utf16Le);
+        }
+        if sample.starts_with(b"\xfe\xff") {
+            return FileType::Text(TextEncoding::Utf16Be);
+        }
+        if sample.contains(&0) {
+            return FileType::Binary(BinaryKind::Unknown);
+        }
+        match std::str::from_utf8(sample) {
+            Ok(_) => return FileType::Text(TextEncoding::Utf8),
+            // a multi-byte character cut by the end of the sample is still valid UTF-8
+            Err(e) if truncated && e.error_len().is_none() => return FileType::Text(TextEncoding::Utf8),
+            Err(_) => {}
+        }
+        let control_chars = sample
+            .iter()
+            .filter(|&&byte| byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(&byte) || byte == 0x7f)
+            .count();
+        if control_chars * 10 < sample.len() {
+            FileType::Text(TextEncoding::Latin1)
+        } else {
+            FileType::Binary(BinaryKind::Unknown)
+        }
+    }
+    // true when 'name' is one of NAMES and describes this type
+    pub fn has_name(&self, name: &str) -> bool {
+        match self {
+            FileType::Text(encoding) => name == "text" || name == encoding.name(),
+            FileType::Binary(kind) => name == "binary" || name == kind.name(),
+        }
+    }
+}
+
+pub const DEFAULT_CONTENT_CAP: usize = 1000;
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+// a disk file as it was when scanned, to tell whether the bytes which are not cached can still be read from it
+#[derive(Clone, Debug, PartialEq)]
+struct Source {
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct File {
     name: String,
-    content: Vec<u8>, // max 1000 bytes, rest of the file truncated
+    content: Arc<Vec<u8>>, // cached bytes, at most the content cap when scanned from disk, shared between copies
+    size: u64, // real size of the file, content may hold only its first bytes
+    source: Option<Source>, // where the bytes which are not cached can be read from
     creation_time: u64,
+    metadata: NodeMetadata,
     type_: FileType,
+    hash: OnceLock<String>, // SHA-256 of the whole content in hex, computed the first time it is asked for
 }
 impl Display for File{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self.type_ {
-            FileType::Binary =>  write!(f, "File: name={}, content={:?}, creation_time={}, type=.bin\n", self.name, self.content, self.creation_time),
-            FileType::Text => write!(f, "File: name={}, content={:?}, creation_time={}, type=.txt\n", self.name, self.content, self.creation_time)
-        }
+        writeln!(f, "File: name={}, content={:?}, size={}, creation_time={}, type={}", self.name, self.content, self.size, self.creation_time, self.type_)
     }
 }
 impl PartialEq<Path> for File{
@@ -71,25 +325,22 @@ impl PartialEq<Path> for File{
     }
 }
 impl File {
-    pub fn new(name: String, metadata: fs::Metadata) -> Result<File, CustomError>{
+    pub fn new(name: String, metadata: fs::Metadata, content_cap: usize) -> Result<File, CustomError>{
         let mut content = vec![];
         let file = OpenOptions::new().read(true).open(&name)?;
-        let mut reader = BufReader::new(file.take(1000));
-        let path = Path::new(&name);
-        let extension = match path.extension() {
-            Some(ext) => ext.to_str().ok_or(CustomError::FileOrDirNameNotFound)?,
-            None => "",
-        };
-        let type_ = match extension {
-            "txt" | "md" | "rs" | "py" | "js" | "html" | "css" | "json" | "toml" | "yaml" | "yml" => FileType::Text,
-            _ => FileType::Binary,
-        };
+        let mut reader = BufReader::new(file.take(content_cap.max(SNIFF_SIZE) as u64));
         reader.read_to_end(&mut content)?;
+        let type_ = FileType::detect(&content[..content.len().min(SNIFF_SIZE)], metadata.len() > SNIFF_SIZE as u64);
+        content.truncate(content_cap);
         Ok(File {
+            source: Some(Source { path: PathBuf::from(&name), size: metadata.len(), modified: metadata.modified().ok() }),
             name,
-            content,
-            creation_time: timestamp_to_u64(metadata.created()?)?,
+            content: Arc::new(content),
+            size: metadata.len(),
+            creation_time: creation_time_of(&metadata),
+            metadata: NodeMetadata::from_fs(&metadata),
             type_,
+            hash: OnceLock::new(),
         })
     }
     pub fn new_from_file(path: &Path, file: &File) -> Result<File, CustomError> {
@@ -100,21 +351,139 @@ impl File {
         Ok(File {
             name,
             content: file.content.clone(),
+            size: file.size,
+            source: file.source.clone(),
             creation_time: file.creation_time,
-            type_: {
-                match file.type_ {
-                    FileType::Binary => FileType::Binary,
-                    FileType::Text => FileType::Text
-                }
-            },
+            metadata: file.metadata,
+            type_: file.type_,
+            hash: file.hash.clone(),
         })
     }
+    pub fn is_fully_loaded(&self) -> bool { self.content.len() as u64 == self.size }
+    // fails when the disk file was changed since the scan, rather than mixing its new bytes with the cached ones
+    fn open_source(&self) -> Result<fs::File, CustomError> {
+        let source = self.source.as_ref().ok_or(CustomError::FileNotFound)?;
+        let file = fs::File::open(&source.path)?;
+        let metadata = file.metadata()?;
+        if metadata.len() != source.size || metadata.modified().ok() != source.modified {
+            return Err(CustomError::SourceChanged(source.path.clone()));
+        }
+        Ok(file)
+    }
+    // the whole content, read from the source when it is not all cached
+    pub fn read_content(&self) -> Result<Cow<'_, [u8]>, CustomError> {
+        if self.is_fully_loaded() {
+            return Ok(Cow::Borrowed(self.content.as_slice()));
+        }
+        let mut content = Vec::with_capacity(self.size as usize);
+        self.open_source()?.read_to_end(&mut content)?;
+        Ok(Cow::Owned(content))
+    }
+    // the whole content as a stream, from the cache when it holds it all
+    pub fn reader(&self) -> Result<Box<dyn Read + '_>, CustomError> {
+        if self.is_fully_loaded() {
+            return Ok(Box::new(self.content.as_slice()));
+        }
+        Ok(Box::new(self.open_source()?))
+    }
+    // the content is streamed, so hashing a file scanned with a small cap does not load it
+    pub fn get_hash(&self) -> Result<&str, CustomError> {
+        if let Some(hash) = self.hash.get() {
+            return Ok(hash);
+        }
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut self.reader()?, &mut hasher)?;
+        let hash = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(self.hash.get_or_init(|| hash))
+    }
+    // caches the whole content, whatever the cap it was scanned with
+    pub fn load_content(&mut self) -> Result<(), CustomError> {
+        if !self.is_fully_loaded() {
+            self.content = Arc::new(self.read_content()?.into_owned());
+            self.size = self.content.len() as u64;
+        }
+        Ok(())
+    }
+    // streams the content in chunks, so that the whole file is never held in memory
+    pub fn content_contains(&self, needle: &[u8]) -> Result<bool, CustomError> {
+        let contains = |haystack: &[u8]| needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle);
+        if self.is_fully_loaded() {
+            return Ok(contains(&self.content));
+        }
+        let mut reader = BufReader::new(self.open_source()?);
+        let mut window: Vec<u8> = vec![];
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        loop {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(false);
+            }
+            window.extend_from_slice(&chunk[..read]);
+            if contains(&window) {
+                return Ok(true);
+            }
+            // keep just enough bytes to find a needle split across two chunks
+            let keep = window.len().min(needle.len().saturating_sub(1));
+            window.drain(..window.len() - keep);
+        }
+    }
     pub fn get_name(&self) -> &str { &self.name }
     pub fn get_filetype(&self) -> &FileType { &self.type_ }
     pub fn get_content(&self) -> &Vec<u8> { &self.content }
+    // the disk file the content which is not cached is read from, None once it is all cached
+    pub fn get_source(&self) -> Option<&Path> { self.source.as_ref().filter(|_| !self.is_fully_loaded()).map(|source| source.path.as_path()) }
+    // true when the cached content of the two files is the same memory, e.g. after a copy or a snapshot
+    pub fn shares_content(&self, other: &File) -> bool { Arc::ptr_eq(&self.content, &other.content) }
+    // true when the content which is not cached is read from the same disk file, as it was when both were scanned
+    pub fn shares_source(&self, other: &File) -> bool { self.source.is_some() && self.source == other.source }
+    pub fn get_size(&self) -> u64 { self.size }
     pub fn get_creation_time(&self) -> u64 { self.creation_time }
+    pub fn get_metadata(&self) -> &NodeMetadata { &self.metadata }
     pub fn set_name(&mut self, name: String) { self.name = name; }
-    pub fn set_content(&mut self, content: Vec<u8>) { self.content = content; }
+    pub fn set_content(&mut self, content: Vec<u8>) {
+        self.size = content.len() as u64;
+        self.source = None;
+        self.content = Arc::new(content);
+        self.hash = OnceLock::new();
+    }
     pub fn set_creation_time(&mut self, creation_time: u64) { self.creation_time = creation_time; }
+    pub fn set_metadata(&mut self, metadata: NodeMetadata) { self.metadata = metadata; }
     pub fn set_type_(&mut self, type_: FileType) { self.type_ = type_; }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::dir::tests::scratch_dir;
+
+    #[test]
+    fn content_past_the_cap_is_read_from_disk_on_demand() {
+        let dir = scratch_dir("lazy_content");
+        let path = dir.join("big.txt");
+        let mut content = vec![b'a'; 3 * READ_CHUNK_SIZE];
+        content[READ_CHUNK_SIZE - 2..READ_CHUNK_SIZE + 2].copy_from_slice(b"edge");
+        fs::write(&path, &content).unwrap();
+        let mut file = File::new(path.to_str().unwrap().to_string(), fs::metadata(&path).unwrap(), 1000).unwrap();
+        assert_eq!((file.get_content().len(), file.get_size()), (1000, content.len() as u64));
+        assert_eq!(file.get_source(), Some(path.as_path()));
+        // a needle split across two chunks of the stream is found
+        assert!(file.content_contains(b"edge").unwrap());
+        assert!(!file.content_contains(b"missing").unwrap());
+        assert_eq!(file.read_content().unwrap().len(), content.len());
+        file.load_content().unwrap();
+        assert!(file.is_fully_loaded() && file.get_source().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_source_changed_since_the_scan_is_not_read() {
+        let dir = scratch_dir("changed_source");
+        let path = dir.join("big.txt");
+        fs::write(&path, vec![b'a'; 2000]).unwrap();
+        let file = File::new(path.to_str().unwrap().to_string(), fs::metadata(&path).unwrap(), 1000).unwrap();
+        fs::write(&path, vec![b'b'; 3000]).unwrap();
+        assert!(matches!(file.read_content(), Err(CustomError::SourceChanged(ref changed)) if *changed == path));
+        assert!(matches!(file.content_contains(b"b"), Err(CustomError::SourceChanged(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
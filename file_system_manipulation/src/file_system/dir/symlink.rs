@@ -0,0 +1,84 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::file_system::dir::file::{creation_time_of, CustomError, NodeMetadata};
+
+/* A link to another entry. The target is kept as written, so a relative target is relative to the dir which
+   contains the link, while a target starting with a separator is relative to the root of the FileSystem. On disk
+   such a target would be relative to the root of the host, so targets are translated when read and written. */
+#[derive(Default, Clone)]
+pub struct Symlink {
+    name: String,
+    target: PathBuf,
+    creation_time: u64,
+    metadata: NodeMetadata,
+}
+impl Display for Symlink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Symlink: name={}, target={}, creation_time={}", self.name, self.target.display(), self.creation_time)
+    }
+}
+impl Symlink {
+    pub fn new(path: &Path, target: &Path, creation_time: u64) -> Result<Symlink, CustomError> {
+        Ok(Symlink {
+            name: path.to_str().ok_or(CustomError::FileOrDirNameNotFound)?.to_string(),
+            target: target.to_path_buf(),
+            creation_time,
+            metadata: NodeMetadata::new(creation_time),
+        })
+    }
+    /* 'path' must be the link itself, not what it points to, and 'disk_root' the canonical path of the dir the tree
+       is read from. An absolute target inside it starts from the root of the FileSystem, e.g. "/folder0_0", any
+       other absolute target is made relative to the dir of the link, so that it still points to the same entry. */
+    pub fn read(path: &Path, disk_root: &Path) -> Result<Symlink, CustomError> {
+        let metadata = fs::symlink_metadata(path)?;
+        let target = fs::read_link(path)?;
+        let target = if target.is_absolute() {
+            let canonical = fs::canonicalize(&target).unwrap_or_else(|_| target.clone());
+            match target.strip_prefix(disk_root).or_else(|_| canonical.strip_prefix(disk_root)) {
+                Ok(relative) => Path::new("/").join(relative),
+                Err(_) => match path.parent().map(fs::canonicalize) {
+                    Some(Ok(link_dir)) => relative_path(&link_dir, &target),
+                    _ => target,
+                },
+            }
+        } else {
+            target
+        };
+        let mut link = Symlink::new(path, &target, creation_time_of(&metadata))?;
+        link.metadata = NodeMetadata::from_fs(&metadata);
+        Ok(link)
+    }
+    /* The target to write on disk for a link 'depth' dirs below the root being written: a target starting from the
+       root of the FileSystem is made relative to the dir of the link, the others are written as they are. */
+    pub fn disk_target(&self, depth: usize) -> PathBuf {
+        let target = self.target.to_string_lossy().replace('\\', "/");
+        let Some(below_root) = target.strip_prefix('/') else { return self.target.clone() };
+        let mut disk_target: PathBuf = std::iter::repeat_n("..", depth).collect();
+        disk_target.extend(below_root.split('/').filter(|name| !name.is_empty()));
+        if disk_target.as_os_str().is_empty() {
+            disk_target.push(".");
+        }
+        disk_target
+    }
+
+    pub fn get_name(&self) -> &str { &self.name }
+    pub fn get_target(&self) -> &Path { &self.target }
+    pub fn get_creation_time(&self) -> u64 { self.creation_time }
+    pub fn get_metadata(&self) -> &NodeMetadata { &self.metadata }
+    pub fn set_name(&mut self, name: String) { self.name = name; }
+    pub fn set_creation_time(&mut self, creation_time: u64) { self.creation_time = creation_time; }
+    pub fn set_metadata(&mut self, metadata: NodeMetadata) { self.metadata = metadata; }
+}
+
+// the path leading from the dir 'from' to 'to', both absolute; 'to' itself when they share no root, e.g. on windows
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let (from, to): (Vec<_>, Vec<_>) = (from.components().collect(), to.components().collect());
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return to.iter().collect();
+    }
+    let mut relative: PathBuf = std::iter::repeat_n(Component::ParentDir, from.len() - common).collect();
+    relative.extend(&to[common..]);
+    relative
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::file_system::dir::{Dir, Node};
+use crate::file_system::dir::file::File;
+use crate::file_system::walk::Walk;
+
+// files holding the same bytes, sorted by path
+#[derive(Clone, Debug)]
+pub struct DuplicateGroup {
+    hash: String,
+    size: u64,
+    paths: Vec<PathBuf>,
+}
+impl Display for DuplicateGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} copies of {} bytes, {} bytes wasted)", self.hash, self.paths.len(), self.size, self.wasted())?;
+        for path in &self.paths {
+            write!(f, "\n\t{}", path.display())?;
+        }
+        Ok(())
+    }
+}
+impl DuplicateGroup {
+    pub fn get_hash(&self) -> &str { &self.hash }
+    pub fn get_size(&self) -> u64 { self.size }
+    pub fn get_paths(&self) -> &Vec<PathBuf> { &self.paths }
+    // the space taken by all the copies but one
+    pub fn wasted(&self) -> u64 { self.size * (self.paths.len() as u64 - 1) }
+}
+
+// the groups waste the most space first
+#[derive(Clone, Debug, Default)]
+pub struct Duplicates {
+    groups: Vec<DuplicateGroup>,
+    unreadable: Vec<PathBuf>, // files which could not be hashed, e.g. changed on disk since the scan, sorted
+}
+impl Display for Duplicates {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for group in &self.groups {
+            writeln!(f, "{}", group)?;
+        }
+        let copies: usize = self.groups.iter().map(|group| group.paths.len() - 1).sum();
+        write!(f, "{} bytes wasted by {} duplicates in {} groups", self.wasted(), copies, self.groups.len())?;
+        if !self.unreadable.is_empty() {
+            write!(f, ", {} files could not be read:", self.unreadable.len())?;
+            for path in &self.unreadable {
+                write!(f, "\n\t{}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+impl Duplicates {
+    pub fn get_groups(&self) -> &Vec<DuplicateGroup> { &self.groups }
+    pub fn get_unreadable(&self) -> &Vec<PathBuf> { &self.unreadable }
+    pub fn wasted(&self) -> u64 { self.groups.iter().map(DuplicateGroup::wasted).sum() }
+}
+
+/* Groups the files below 'root' by content. Only files sharing their size with another one are hashed, and each
+   hash is kept in its File, so searching again or querying by hash does not read the contents twice. Empty files
+   are skipped, as they waste no space, and the files which cannot be read are listed apart instead of stopping
+   the search. */
+pub fn find_duplicates(root: &Dir) -> Duplicates {
+    let mut by_size: HashMap<u64, Vec<(&Path, &File)>> = HashMap::new();
+    for (path, _, node) in Walk::new(root) {
+        if let Node::File(file) = node {
+            if file.get_size() > 0 {
+                by_size.entry(file.get_size()).or_default().push((path, file));
+            }
+        }
+    }
+    let mut by_hash: HashMap<&str, DuplicateGroup> = HashMap::new();
+    let mut unreadable = vec![];
+    for (size, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        for (path, file) in files {
+            let Ok(hash) = file.get_hash() else {
+                unreadable.push(path.to_path_buf());
+                continue;
+            };
+            by_hash.entry(hash)
+                .or_insert_with(|| DuplicateGroup { hash: hash.to_string(), size, paths: vec![] })
+                .paths.push(path.to_path_buf());
+        }
+    }
+    let mut groups: Vec<DuplicateGroup> = by_hash.into_values().filter(|group| group.paths.len() > 1).collect();
+    for group in &mut groups {
+        group.paths.sort();
+    }
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.paths.cmp(&b.paths)));
+    unreadable.sort();
+    Duplicates { groups, unreadable }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+    use crate::file_system::dir::ScanOptions;
+    use crate::file_system::dir::tests::{file, scratch_dir, tree};
+
+    #[test]
+    fn groups_identical_files_and_sums_the_wasted_space() {
+        let mut root = tree(&["a/", "empty1", "other"]);
+        for path in ["root/x.txt", "root/a/y.txt", "root/a/z.txt"] {
+            root.new_file(Path::new(path), &file(b"same bytes")).unwrap();
+        }
+        root.new_file(Path::new("root/a/empty2"), &file(b"")).unwrap();
+        root.get_file(Path::new("root/empty1")).unwrap().set_content(vec![]);
+        let duplicates = find_duplicates(&root);
+        assert_eq!(duplicates.get_groups().len(), 1);
+        let group = &duplicates.get_groups()[0];
+        assert_eq!(group.get_paths(), &[PathBuf::from("root/a/y.txt"), PathBuf::from("root/a/z.txt"), PathBuf::from("root/x.txt")]);
+        assert_eq!(group.get_hash(), file(b"same bytes").get_hash().unwrap());
+        assert_eq!(duplicates.wasted(), 20);
+    }
+
+    #[test]
+    fn files_changed_since_the_scan_are_listed_apart() {
+        let dir = scratch_dir("duplicates");
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            fs::write(dir.join(name), vec![7; 2000]).unwrap();
+        }
+        let root = Dir::scan(dir.to_str().unwrap(), &ScanOptions::default()).unwrap();
+        fs::write(dir.join("c.bin"), vec![8; 2001]).unwrap();
+        let duplicates = find_duplicates(&root);
+        assert_eq!(duplicates.get_groups()[0].get_paths().len(), 2);
+        assert_eq!(duplicates.get_unreadable(), &[dir.join("c.bin")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
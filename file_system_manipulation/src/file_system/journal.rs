@@ -0,0 +1,176 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::file_system::dir::{Dir, Node};
+use crate::file_system::dir::file::CustomError;
+
+// what was at 'path' before and after an operation, None meaning that there was nothing
+#[derive(Clone)]
+pub struct Edit {
+    path: PathBuf,
+    before: Option<Node>,
+    after: Option<Node>,
+}
+impl Edit {
+    pub fn get_path(&self) -> &Path { &self.path }
+    pub fn get_before(&self) -> Option<&Node> { self.before.as_ref() }
+    pub fn get_after(&self) -> Option<&Node> { self.after.as_ref() }
+}
+
+// a mutating call of FileSystem, e.g. "mv my_fs/a -> my_fs/b", with the nodes it changed
+#[derive(Clone)]
+pub struct Operation {
+    description: String,
+    edits: Vec<Edit>,
+}
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+impl Operation {
+    pub fn new(description: String) -> Operation {
+        Operation { description, edits: vec![] }
+    }
+    pub fn add_edit(&mut self, path: &Path, before: Option<Node>, after: Option<Node>) {
+        self.edits.push(Edit { path: path.to_path_buf(), before, after });
+    }
+    pub fn get_description(&self) -> &str { &self.description }
+    pub fn get_edits(&self) -> &Vec<Edit> { &self.edits }
+
+    // the edits are reverted last to first, so that e.g. a moved node leaves its destination before coming back
+    pub fn undo(&self, root: &mut Dir) -> Result<(), CustomError> {
+        apply(root, self.edits.iter().rev().map(|edit| (&edit.path, &edit.after, &edit.before)).collect())
+    }
+    pub fn redo(&self, root: &mut Dir) -> Result<(), CustomError> {
+        apply(root, self.edits.iter().map(|edit| (&edit.path, &edit.before, &edit.after)).collect())
+    }
+}
+
+/* Replaces each node at 'path' with 'to'. The tree may have been changed bypassing the journal, e.g. by a Watcher,
+   so a step can fail: the steps already applied are then reverted to 'from', leaving the tree as it was. */
+fn apply(root: &mut Dir, steps: Vec<(&PathBuf, &Option<Node>, &Option<Node>)>) -> Result<(), CustomError> {
+    for (i, (path, _, to)) in steps.iter().enumerate() {
+        if let Err(e) = replace(root, path, (*to).clone()) {
+            for (path, from, _) in steps[..i].iter().rev() {
+                replace(root, path, (*from).clone())?;
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// fails only when the parent is missing, before changing anything
+fn replace(root: &mut Dir, path: &Path, node: Option<Node>) -> Result<(), CustomError> {
+    let parent = path.parent().and_then(|parent| root.get_dir_mut(parent)).ok_or(CustomError::FileOrDirNameNotFound)?;
+    parent.take_child(path);
+    if let Some(node) = node {
+        parent.add_child(node);
+    }
+    Ok(())
+}
+
+// where a transaction starts: how many operations were done and the ones which could be redone
+pub struct Checkpoint {
+    done: usize,
+    undone: Vec<Operation>,
+}
+
+/* The operations done on a FileSystem, which can be undone, and the ones undone, which can be redone until a new
+   operation is done. Removed nodes are kept with their whole content, also the part which was not cached. */
+#[derive(Default)]
+pub struct Journal {
+    done: Vec<Operation>,
+    undone: Vec<Operation>,
+}
+impl Display for Journal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for operation in self.done.iter() {
+            writeln!(f, "  {}", operation)?;
+        }
+        for operation in self.undone.iter().rev() {
+            writeln!(f, "  (undone) {}", operation)?;
+        }
+        Ok(())
+    }
+}
+impl Journal {
+    pub fn push(&mut self, operation: Operation) {
+        self.done.push(operation);
+        self.undone.clear();
+    }
+    // the last operation done, which is moved among the undone ones
+    pub fn undo(&mut self, root: &mut Dir) -> Result<Option<&Operation>, CustomError> {
+        let Some(operation) = self.done.pop() else { return Ok(None) };
+        if let Err(e) = operation.undo(root) {
+            self.done.push(operation);
+            return Err(e);
+        }
+        self.undone.push(operation);
+        Ok(self.undone.last())
+    }
+    pub fn redo(&mut self, root: &mut Dir) -> Result<Option<&Operation>, CustomError> {
+        let Some(operation) = self.undone.pop() else { return Ok(None) };
+        if let Err(e) = operation.redo(root) {
+            self.undone.push(operation);
+            return Err(e);
+        }
+        self.done.push(operation);
+        Ok(self.done.last())
+    }
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint { done: self.done.len(), undone: std::mem::take(&mut self.undone) }
+    }
+    // the operations done since the checkpoint become a single one, undone and redone as a whole
+    pub fn squash(&mut self, checkpoint: Checkpoint) {
+        let operations = self.done.split_off(checkpoint.done.min(self.done.len()));
+        if operations.is_empty() {
+            self.undone = checkpoint.undone;
+        } else {
+            self.done.push(merge(operations));
+        }
+    }
+    /* The operations done since the checkpoint are undone and forgotten. When they cannot be undone, e.g. the tree
+       was changed behind the journal, the tree is left as it is and they are kept, squashed, to match it. */
+    pub fn rollback(&mut self, checkpoint: Checkpoint, root: &mut Dir) -> Result<(), CustomError> {
+        let operations = merge(self.done.split_off(checkpoint.done.min(self.done.len())));
+        if let Err(e) = operations.undo(root) {
+            self.done.push(operations);
+            return Err(e);
+        }
+        self.undone = checkpoint.undone;
+        Ok(())
+    }
+    // oldest first
+    pub fn get_done(&self) -> &Vec<Operation> { &self.done }
+    // next to be redone last
+    pub fn get_undone(&self) -> &Vec<Operation> { &self.undone }
+}
+
+fn merge(operations: Vec<Operation>) -> Operation {
+    let descriptions: Vec<&str> = operations.iter().map(|operation| operation.get_description()).collect();
+    let mut merged = Operation::new(format!("transaction [{}]", descriptions.join(", ")));
+    merged.edits = operations.into_iter().flat_map(|operation| operation.edits).collect();
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::dir::tests::tree;
+
+    #[test]
+    fn a_rollback_which_cannot_undo_keeps_the_operations() {
+        let mut root = tree(&["a/"]);
+        let mut journal = Journal::default();
+        let checkpoint = journal.checkpoint();
+        root.mk_dir(Path::new("root/a/x")).unwrap();
+        let mut operation = Operation::new("mk_dir root/a/x".to_string());
+        operation.add_edit(Path::new("root/a/x"), None, root.get_node(Path::new("root/a/x")).cloned());
+        journal.push(operation);
+        // changed behind the journal: the parent of the edit is gone
+        root.rm_dir(Path::new("root/a"), true).unwrap();
+        assert!(journal.rollback(checkpoint, &mut root).is_err());
+        assert_eq!(journal.get_done().len(), 1);
+    }
+}
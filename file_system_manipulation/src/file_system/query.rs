@@ -0,0 +1,336 @@
+use std::fmt::{Display, Formatter};
+use crate::file_system::Queries;
+use crate::file_system::dir::Node;
+
+#[derive(Debug)]
+pub enum QueryErrorReason {
+    MissingColon,
+    UnknownKey(String),
+    NotANumber(String),
+    InvalidPattern(String),
+    UnknownFileType(String),
+    NotAHash(String),
+    UnterminatedQuote,
+    UnclosedParenthesis,
+    UnexpectedToken(String),
+    UnexpectedEnd,
+}
+impl Display for QueryErrorReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryErrorReason::MissingColon => write!(f, "expected a 'key:value' pair"),
+            QueryErrorReason::UnknownKey(key) => write!(f, "unknown key '{}'", key),
+            QueryErrorReason::NotANumber(value) => write!(f, "'{}' is not a valid number", value),
+            QueryErrorReason::InvalidPattern(e) => write!(f, "invalid pattern, {}", e),
+            QueryErrorReason::UnknownFileType(name) => write!(f, "unknown file type '{}'", name),
+            QueryErrorReason::NotAHash(value) => write!(f, "'{}' is not a hex hash or hash prefix", value),
+            QueryErrorReason::UnterminatedQuote => write!(f, "unterminated quote"),
+            QueryErrorReason::UnclosedParenthesis => write!(f, "'(' is never closed"),
+            QueryErrorReason::UnexpectedToken(token) => write!(f, "unexpected '{}'", token),
+            QueryErrorReason::UnexpectedEnd => write!(f, "unexpected end of query"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryError {
+    pub query: String,
+    pub position: usize, // byte offset inside query
+    pub reason: QueryErrorReason,
+}
+impl QueryError {
+    pub fn new(query: &str, position: usize, reason: QueryErrorReason) -> Self {
+        QueryError { query: query.to_string(), position, reason }
+    }
+    fn within(self, query: &str, offset: usize) -> Self {
+        QueryError::new(query, self.position + offset, self.reason)
+    }
+}
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' at position {}: {}", self.query, self.position, self.reason)
+    }
+}
+
+/* Grammar of a query expression (NOT binds tighter than AND, AND tighter than OR):
+    expr    := and ( "OR" and )*
+    and     := not ( "AND" not )*
+    not     := "NOT" not | primary
+    primary := "(" expr ")" | key:value
+   where key is one of name, glob, regex, content, type, hash (hex prefix of the SHA-256), larger, smaller, newer,
+   older, modified-after, modified-before, accessed-after, accessed-before, perm (octal mode bits), owner (uid),
+   group (gid)
+   name:value matches the names containing value, or the names matching it as a glob when it holds one of '*', '?'
+   or '[', e.g. name:*.rs
+   values containing spaces or parentheses must be quoted, e.g. content:"test queries"
+*/
+pub enum QueryExpr<'a> {
+    Query(Queries<'a>),
+    Not(Box<QueryExpr<'a>>),
+    And(Box<QueryExpr<'a>>, Box<QueryExpr<'a>>),
+    Or(Box<QueryExpr<'a>>, Box<QueryExpr<'a>>),
+}
+impl QueryExpr<'_> {
+    pub fn matches(&self, node: &Node) -> bool {
+        match self {
+            QueryExpr::Query(query) => query.matches(node),
+            QueryExpr::Not(expr) => !expr.matches(node),
+            QueryExpr::And(left, right) => left.matches(node) && right.matches(node),
+            QueryExpr::Or(left, right) => left.matches(node) || right.matches(node),
+        }
+    }
+}
+
+// Shell-like glob matching on '/' separated paths:
+//  *      any sequence of characters except '/'
+//  **     any sequence of characters, '/' included ("**/" also matches no directory at all)
+//  ?      any single character except '/'
+//  [a-z]  a character in the class, [!a-z] a character outside it
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_matches_chars(&pattern, &text)
+}
+fn glob_matches_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            if let ['/', after_slash @ ..] = rest {
+                if glob_matches_chars(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob_matches_chars(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob_matches_chars(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => matches!(text, [c, ..] if *c != '/') && glob_matches_chars(rest, &text[1..]),
+        ['[', class @ ..] => match (class.iter().skip(1).position(|&c| c == ']'), text) {
+            (Some(end), [c, text_rest @ ..]) => {
+                let class_body = &class[..end + 1];
+                let (negated, class_body) = match class_body {
+                    ['!', body @ ..] => (true, body),
+                    body => (false, body),
+                };
+                let mut in_class = false;
+                let mut i = 0;
+                while i < class_body.len() {
+                    if i + 2 < class_body.len() && class_body[i + 1] == '-' {
+                        in_class |= class_body[i] <= *c && *c <= class_body[i + 2];
+                        i += 3;
+                    } else {
+                        in_class |= class_body[i] == *c;
+                        i += 1;
+                    }
+                }
+                in_class != negated && glob_matches_chars(&class[end + 2..], text_rest)
+            }
+            (None, [c, text_rest @ ..]) => *c == '[' && glob_matches_chars(class, text_rest),
+            (_, []) => false,
+        },
+        [p, rest @ ..] => matches!(text, [c, ..] if c == p) && glob_matches_chars(rest, &text[1..]),
+    }
+}
+
+pub struct Expression<'a> {
+    source: &'a str,
+    root: QueryExpr<'a>,
+}
+impl<'a> Expression<'a> {
+    pub fn parse(source: &'a str) -> Result<Expression<'a>, QueryError> {
+        let mut parser = Parser { source, tokens: tokenize(source)?, pos: 0 };
+        let root = parser.parse_or()?;
+        if let Some(&(position, token)) = parser.tokens.get(parser.pos) {
+            return Err(parser.unexpected(position, token));
+        }
+        Ok(Expression { source, root })
+    }
+    pub fn to_str(&self) -> &'a str { self.source }
+    pub fn matches(&self, node: &Node) -> bool { self.root.matches(node) }
+}
+
+#[derive(Clone, Copy)]
+enum Token<'a> {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Term(&'a str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token<'_>)>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            '(' => { chars.next(); tokens.push((start, Token::Open)); }
+            ')' => { chars.next(); tokens.push((start, Token::Close)); }
+            c if c.is_whitespace() => { chars.next(); }
+            _ => {
+                let mut end = source.len();
+                let mut quoted = None;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c == '"' {
+                        quoted = if quoted.is_some() { None } else { Some(i) };
+                    } else if quoted.is_none() && (c.is_whitespace() || c == '(' || c == ')') {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                if let Some(quote) = quoted {
+                    return Err(QueryError::new(source, quote, QueryErrorReason::UnterminatedQuote));
+                }
+                tokens.push((start, match &source[start..end] {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    term => Token::Term(term),
+                }));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, Token<'a>)>,
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|&(_, token)| token)
+    }
+    fn unexpected(&self, position: usize, token: Token) -> QueryError {
+        let text = match token {
+            Token::And => "AND",
+            Token::Or => "OR",
+            Token::Not => "NOT",
+            Token::Open => "(",
+            Token::Close => ")",
+            Token::Term(term) => term,
+        };
+        QueryError::new(self.source, position, QueryErrorReason::UnexpectedToken(text.to_string()))
+    }
+    fn parse_or(&mut self) -> Result<QueryExpr<'a>, QueryError> {
+        let mut left = self.parse_and()?;
+        while let Some(Token::Or) = self.peek() {
+            self.pos += 1;
+            left = QueryExpr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+    fn parse_and(&mut self) -> Result<QueryExpr<'a>, QueryError> {
+        let mut left = self.parse_not()?;
+        while let Some(Token::And) = self.peek() {
+            self.pos += 1;
+            left = QueryExpr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+    fn parse_not(&mut self) -> Result<QueryExpr<'a>, QueryError> {
+        if let Some(Token::Not) = self.peek() {
+            self.pos += 1;
+            return Ok(QueryExpr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+    fn parse_primary(&mut self) -> Result<QueryExpr<'a>, QueryError> {
+        let (position, token) = *self.tokens
+            .get(self.pos)
+            .ok_or_else(|| QueryError::new(self.source, self.source.len(), QueryErrorReason::UnexpectedEnd))?;
+        self.pos += 1;
+        match token {
+            Token::Open => {
+                let expr = self.parse_or()?;
+                match self.peek() {
+                    Some(Token::Close) => { self.pos += 1; Ok(expr) },
+                    _ => Err(QueryError::new(self.source, position, QueryErrorReason::UnclosedParenthesis))
+                }
+            }
+            Token::Term(term) => Queries::parse(term)
+                .map(QueryExpr::Query)
+                .map_err(|e| e.within(self.source, position)),
+            _ => Err(self.unexpected(position, token))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+    use crate::file_system::dir::Dir;
+    use crate::file_system::dir::tests::file;
+
+    fn error_of(query: &str) -> QueryError {
+        match Expression::parse(query) {
+            Ok(_) => panic!("'{}' should not parse", query),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_faulty_token() {
+        let e = error_of("name:a AND larger:abc");
+        assert_eq!(e.position, 18);
+        assert!(matches!(e.reason, QueryErrorReason::NotANumber(ref value) if value == "abc"));
+        assert!(matches!(error_of("(name:a OR name:b").reason, QueryErrorReason::UnclosedParenthesis));
+        assert_eq!(error_of("(name:a OR name:b").position, 0);
+        assert!(matches!(error_of("name:a AND").reason, QueryErrorReason::UnexpectedEnd));
+        assert_eq!(error_of("name:a AND").position, 10);
+        assert!(matches!(error_of("name:a AND bogus:b").reason, QueryErrorReason::UnknownKey(ref key) if key == "bogus"));
+        assert_eq!(error_of("name:a AND bogus:b").position, 11);
+        assert!(matches!(error_of("content:\"open").reason, QueryErrorReason::UnterminatedQuote));
+        assert_eq!(error_of("content:\"open").position, 8);
+    }
+
+    #[test]
+    fn terms_without_an_operator_are_rejected() {
+        let e = error_of("name:a larger:abc");
+        assert_eq!(e.position, 7);
+        assert!(matches!(e.reason, QueryErrorReason::UnexpectedToken(ref token) if token == "larger:abc"));
+        assert!(matches!(error_of("content:test queries").reason, QueryErrorReason::UnexpectedToken(_)));
+        assert!(Expression::parse("content:\"test queries\" AND name:file").is_ok());
+    }
+
+    #[test]
+    fn glob_double_star_and_classes() {
+        assert!(glob_matches("**/*.txt", "a.txt"));
+        assert!(glob_matches("**/*.txt", "my_fs/folder/a.txt"));
+        assert!(glob_matches("my_fs/**", "my_fs/folder/a.txt"));
+        assert!(!glob_matches("*.txt", "folder/a.txt"));
+        assert!(glob_matches("file0_[0-9].bin", "file0_7.bin"));
+        assert!(!glob_matches("file0_[0-9].bin", "file0_x.bin"));
+        assert!(glob_matches("file0_[!0-9].bin", "file0_x.bin"));
+        assert!(glob_matches("?.txt", "a.txt"));
+        assert!(!glob_matches("?.txt", "/.txt"));
+    }
+
+    #[test]
+    fn name_patterns_match_the_leaf_or_the_full_path() {
+        let mut root = Dir::new_from_dir(Path::new("/abs/root"), 0).unwrap();
+        root.mk_dir(Path::new("/abs/root/src")).unwrap();
+        root.new_file(Path::new("/abs/root/src/main.rs"), &file(b"fn main() {}")).unwrap();
+        let node = root.get_node(Path::new("/abs/root/src/main.rs")).unwrap();
+        let matches = |query: &str| Queries::parse(query).unwrap().matches(node);
+        assert!(matches("name:main"));
+        assert!(matches("name:*.rs"));
+        assert!(matches("name:ma?n.[r]s"));
+        assert!(!matches("name:*.txt"));
+        assert!(matches("glob:/abs/root/**/*.rs"));
+        assert!(!matches("glob:src/*.rs"));
+        assert!(matches("regex:^/abs/root/src/"));
+    }
+}
@@ -0,0 +1,108 @@
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use crate::file_system::FileSystem;
+use crate::file_system::dir::Node;
+use crate::file_system::dir::file::{CustomError, File};
+
+/* A FileSystem which can be shared between threads, e.g. behind an Arc, following read-copy-update: writers run
+   one at a time on the FileSystem, and each one publishes the tree it leaves as a new view. Readers only take the
+   lock long enough to clone the Arc of the current view, and search it without blocking anyone, so a long search
+   sees the tree as it was when it started. Unchanged subtrees are shared by the views and the FileSystem, a write
+   copies only the dirs on the path to what it changes.
+   The current dir is shared too, so threads had better use full paths or paths starting with '/'. */
+pub struct SharedFileSystem {
+    fs: Mutex<FileSystem>,
+    view: RwLock<Arc<FileSystem>>, // the tree and current dir left by the last write, without journal nor versions
+}
+impl From<FileSystem> for SharedFileSystem {
+    fn from(fs: FileSystem) -> Self {
+        SharedFileSystem::new(fs)
+    }
+}
+impl SharedFileSystem {
+    pub fn new(fs: FileSystem) -> SharedFileSystem {
+        SharedFileSystem { view: RwLock::new(Arc::new(view_of(&fs))), fs: Mutex::new(fs) }
+    }
+    // a writer which panicked does not stop the others, the tree is used as it was left
+    pub fn read(&self) -> Arc<FileSystem> {
+        Arc::clone(&self.view.read().unwrap_or_else(PoisonError::into_inner))
+    }
+    // the whole API, the changes are published to the readers when the guard is dropped
+    pub fn write(&self) -> WriteGuard<'_> {
+        WriteGuard { fs: self.fs.lock().unwrap_or_else(PoisonError::into_inner), view: &self.view }
+    }
+    pub fn into_inner(self) -> FileSystem {
+        self.fs.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // full paths of the nodes found, like try_search
+    pub fn search(&self, queries: &[&str]) -> Result<Vec<PathBuf>, CustomError> {
+        let fs = self.read();
+        let result = fs.try_search(queries)?;
+        Ok(result.get_nodes().iter().map(|node| PathBuf::from(node.get_name())).collect())
+    }
+    pub fn get_file(&self, path: &str) -> Option<File> {
+        match self.read().get_node(path)? {
+            Node::File(file) => Some(file.clone()),
+            _ => None
+        }
+    }
+    pub fn mk_dir(&self, path: &str) -> Result<(), CustomError> {
+        self.write().mk_dir(path)
+    }
+    pub fn new_file(&self, path: &str, file: File) -> Result<(), CustomError> {
+        self.write().new_file(path, file)
+    }
+    pub fn rm_file(&self, path: &str) -> Result<(), CustomError> {
+        self.write().rm_file(path)
+    }
+    pub fn mv(&self, src: &str, dst: &str) -> Result<(), CustomError> {
+        self.write().mv(src, dst)
+    }
+}
+
+pub struct WriteGuard<'a> {
+    fs: MutexGuard<'a, FileSystem>,
+    view: &'a RwLock<Arc<FileSystem>>,
+}
+impl Deref for WriteGuard<'_> {
+    type Target = FileSystem;
+    fn deref(&self) -> &FileSystem { &self.fs }
+}
+impl DerefMut for WriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut FileSystem { &mut self.fs }
+}
+impl Drop for WriteGuard<'_> {
+    // the view is built before taking the lock, which is only held to swap it in
+    fn drop(&mut self) {
+        let view = Arc::new(view_of(&self.fs));
+        *self.view.write().unwrap_or_else(PoisonError::into_inner) = view;
+    }
+}
+
+fn view_of(fs: &FileSystem) -> FileSystem {
+    FileSystem { root: fs.root.clone(), cwd: fs.cwd.clone(), ..FileSystem::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::dir::tests::{file, tree};
+
+    #[test]
+    fn readers_keep_the_view_they_started_with() {
+        let shared = SharedFileSystem::new(FileSystem { root: tree(&["a/"]), ..FileSystem::default() });
+        let before = shared.read();
+        shared.new_file("/a/x.txt", file(b"x")).unwrap();
+        assert!(before.get_node("/a/x.txt").is_none());
+        assert_eq!(shared.search(&["name:x.txt"]).unwrap(), [PathBuf::from("root/a/x.txt")]);
+        // a search does not hold the lock, writers go on meanwhile
+        let view = shared.read();
+        let found = view.search(&["name:a"]);
+        shared.rm_file("/a/x.txt").unwrap();
+        assert_eq!(found.get_nodes().len(), 2);
+        assert!(shared.get_file("/a/x.txt").is_none());
+        assert_eq!(shared.into_inner().get_journal().get_done().len(), 2);
+    }
+}
@@ -0,0 +1,337 @@
+/* Snapshot file format (all integers little endian):
+
+    header, 28 BYTES:
+        - magic      -> 8 bytes, "VFSSNAP\0"
+        - version    -> u16, 1
+        - reserved   -> u16, always 0
+        - entries    -> u32, number of Dir and File entries in the body
+        - body size  -> u64, length in bytes of the body
+        - checksum   -> u32, CRC-32 (IEEE) of the body
+
+    body, the root Dir followed by all its descendants in depth-first pre-order. Each entry is:
+        - kind           -> u8, 0 = Dir, 1 = File, 2 = Symlink
+        - name length    -> u32
+        - name           -> utf-8 bytes, the full path of the entry
+        - creation_time  -> u64
+        - metadata
+            - modified time -> u64
+            - accessed time -> u64
+            - disk size     -> u64
+            - unix fields   -> u8, 1 if mode, uid, gid and inode follow, 0 otherwise
+            - mode, uid, gid -> u32 each, inode -> u64
+      then, for a Dir:
+        - children       -> u32, number of entries which follow and belong to this Dir
+      or, for a File:
+        - type           -> u8, Text:   0 = utf-8, 1 = utf-8 with BOM, 2 = utf-16le, 3 = utf-16be, 4 = latin-1
+                                Binary: 16 = png, 17 = jpeg, 18 = gif, 19 = pdf, 20 = zip, 21 = gzip, 22 = elf,
+                                        23 = exe, 31 = unknown
+        - content length -> u64
+        - content        -> raw bytes
+      or, for a Symlink:
+        - target length  -> u32
+        - target         -> utf-8 bytes, as stored in the link
+*/
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::file_system::dir::{Dir, Node};
+use crate::file_system::dir::file::{BinaryKind, CustomError, File, FileType, NodeMetadata, TextEncoding};
+use crate::file_system::dir::symlink::Symlink;
+
+const MAGIC: &[u8; 8] = b"VFSSNAP\0";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 28;
+const KIND_DIR: u8 = 0;
+const KIND_FILE: u8 = 1;
+const KIND_SYMLINK: u8 = 2;
+
+pub fn save(root: &Dir, path: &Path) -> Result<(), CustomError> {
+    let mut body = vec![];
+    let entries = write_dir(root, &mut body)?;
+    let mut snapshot = Vec::with_capacity(HEADER_SIZE + body.len());
+    snapshot.extend_from_slice(MAGIC);
+    snapshot.extend_from_slice(&VERSION.to_le_bytes());
+    snapshot.extend_from_slice(&0u16.to_le_bytes());
+    snapshot.extend_from_slice(&entries.to_le_bytes());
+    snapshot.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    snapshot.extend_from_slice(&crc32(&body).to_le_bytes());
+    snapshot.extend_from_slice(&body);
+    fs::write(path, snapshot)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Dir, CustomError> {
+    let snapshot = fs::read(path)?;
+    if snapshot.len() < HEADER_SIZE || &snapshot[0..8] != MAGIC {
+        return Err(CustomError::InvalidSnapshot("not a snapshot file".to_string()));
+    }
+    let mut header = Reader { bytes: &snapshot[8..HEADER_SIZE], pos: 0 };
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(CustomError::InvalidSnapshot(format!("unsupported version {}", version)));
+    }
+    header.u16()?;
+    let entries = header.u32()?;
+    let body_size = header.u64()?;
+    let checksum = header.u32()?;
+    let body = &snapshot[HEADER_SIZE..];
+    if body.len() as u64 != body_size {
+        return Err(CustomError::InvalidSnapshot("truncated body".to_string()));
+    }
+    if crc32(body) != checksum {
+        return Err(CustomError::InvalidSnapshot("checksum mismatch".to_string()));
+    }
+    let mut reader = Reader { bytes: body, pos: 0 };
+    let mut read_entries = 0;
+    let root = match reader.node(&mut read_entries)? {
+        Node::Dir(dir) => Arc::unwrap_or_clone(dir),
+        _ => return Err(CustomError::InvalidSnapshot("root is not a directory".to_string())),
+    };
+    if read_entries != entries || reader.pos != body.len() {
+        return Err(CustomError::InvalidSnapshot("entry count mismatch".to_string()));
+    }
+    Ok(root)
+}
+
+fn write_header(kind: u8, name: &str, creation_time: u64, metadata: &NodeMetadata, out: &mut Vec<u8>) {
+    out.push(kind);
+    out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&creation_time.to_le_bytes());
+    out.extend_from_slice(&metadata.modified_time.to_le_bytes());
+    out.extend_from_slice(&metadata.accessed_time.to_le_bytes());
+    out.extend_from_slice(&metadata.disk_size.to_le_bytes());
+    match (metadata.mode, metadata.uid, metadata.gid, metadata.inode) {
+        (Some(mode), Some(uid), Some(gid), Some(inode)) => {
+            out.push(1);
+            out.extend_from_slice(&mode.to_le_bytes());
+            out.extend_from_slice(&uid.to_le_bytes());
+            out.extend_from_slice(&gid.to_le_bytes());
+            out.extend_from_slice(&inode.to_le_bytes());
+        }
+        _ => out.push(0),
+    }
+}
+
+// returns the number of entries written
+fn write_dir(dir: &Dir, out: &mut Vec<u8>) -> Result<u32, CustomError> {
+    write_header(KIND_DIR, dir.get_name(), dir.get_creation_time(), dir.get_metadata(), out);
+    out.extend_from_slice(&(dir.get_children().len() as u32).to_le_bytes());
+    let mut entries = 1;
+    for child in dir.get_children() {
+        entries += match child {
+            Node::Dir(child) => write_dir(child, out)?,
+            Node::File(child) => { write_file(child, out)?; 1 },
+            Node::Symlink(child) => { write_symlink(child, out)?; 1 },
+        };
+    }
+    Ok(entries)
+}
+
+// the whole content is stored, also the bytes which were not cached in memory
+fn write_file(file: &File, out: &mut Vec<u8>) -> Result<(), CustomError> {
+    write_header(KIND_FILE, file.get_name(), file.get_creation_time(), file.get_metadata(), out);
+    out.push(type_to_code(file.get_filetype()));
+    let content = file.read_content()?;
+    out.extend_from_slice(&(content.len() as u64).to_le_bytes());
+    out.extend_from_slice(&content);
+    Ok(())
+}
+
+fn write_symlink(link: &Symlink, out: &mut Vec<u8>) -> Result<(), CustomError> {
+    write_header(KIND_SYMLINK, link.get_name(), link.get_creation_time(), link.get_metadata(), out);
+    let target = link.get_target().to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+    out.extend_from_slice(&(target.len() as u32).to_le_bytes());
+    out.extend_from_slice(target.as_bytes());
+    Ok(())
+}
+
+fn type_to_code(type_: &FileType) -> u8 {
+    match type_ {
+        FileType::Text(TextEncoding::Utf8) => 0,
+        FileType::Text(TextEncoding::Utf8Bom) => 1,
+        FileType::Text(TextEncoding::Utf16Le) => 2,
+        FileType::Text(TextEncoding::Utf16Be) => 3,
+        FileType::Text(TextEncoding::Latin1) => 4,
+        FileType::Binary(BinaryKind::Png) => 16,
+        FileType::Binary(BinaryKind::Jpeg) => 17,
+        FileType::Binary(BinaryKind::Gif) => 18,
+        FileType::Binary(BinaryKind::Pdf) => 19,
+        FileType::Binary(BinaryKind::Zip) => 20,
+        FileType::Binary(BinaryKind::Gzip) => 21,
+        FileType::Binary(BinaryKind::Elf) => 22,
+        FileType::Binary(BinaryKind::Exe) => 23,
+        FileType::Binary(BinaryKind::Unknown) => 31,
+    }
+}
+
+fn type_from_code(code: u8) -> Result<FileType, CustomError> {
+    let type_ = match code {
+        0 => FileType::Text(TextEncoding::Utf8),
+        1 => FileType::Text(TextEncoding::Utf8Bom),
+        2 => FileType::Text(TextEncoding::Utf16Le),
+        3 => FileType::Text(TextEncoding::Utf16Be),
+        4 => FileType::Text(TextEncoding::Latin1),
+        16 => FileType::Binary(BinaryKind::Png),
+        17 => FileType::Binary(BinaryKind::Jpeg),
+        18 => FileType::Binary(BinaryKind::Gif),
+        19 => FileType::Binary(BinaryKind::Pdf),
+        20 => FileType::Binary(BinaryKind::Zip),
+        21 => FileType::Binary(BinaryKind::Gzip),
+        22 => FileType::Binary(BinaryKind::Elf),
+        23 => FileType::Binary(BinaryKind::Exe),
+        31 => FileType::Binary(BinaryKind::Unknown),
+        _ => return Err(CustomError::InvalidSnapshot(format!("unknown file type {}", code))),
+    };
+    Ok(type_)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], CustomError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| CustomError::InvalidSnapshot("unexpected end of data".to_string()))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, CustomError> { Ok(self.take(1)?[0]) }
+    fn u16(&mut self) -> Result<u16, CustomError> { Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap())) }
+    fn u32(&mut self) -> Result<u32, CustomError> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    fn u64(&mut self) -> Result<u64, CustomError> { Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) }
+    // a u32 length followed by utf-8 bytes
+    fn string(&mut self) -> Result<String, CustomError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| CustomError::InvalidSnapshot("name is not valid utf-8".to_string()))
+    }
+    fn metadata(&mut self) -> Result<NodeMetadata, CustomError> {
+        let mut metadata = NodeMetadata {
+            modified_time: self.u64()?,
+            accessed_time: self.u64()?,
+            disk_size: self.u64()?,
+            ..NodeMetadata::default()
+        };
+        if self.u8()? == 1 {
+            metadata.mode = Some(self.u32()?);
+            metadata.uid = Some(self.u32()?);
+            metadata.gid = Some(self.u32()?);
+            metadata.inode = Some(self.u64()?);
+        }
+        Ok(metadata)
+    }
+    fn node(&mut self, read_entries: &mut u32) -> Result<Node, CustomError> {
+        *read_entries += 1;
+        let kind = self.u8()?;
+        let name = self.string()?;
+        let creation_time = self.u64()?;
+        let metadata = self.metadata()?;
+        match kind {
+            KIND_DIR => {
+                let mut dir = Dir::new_from_dir(Path::new(&name), creation_time)?;
+                dir.set_metadata(metadata);
+                for _ in 0..self.u32()? {
+                    dir.add_child(self.node(read_entries)?);
+                }
+                Ok(Node::Dir(Arc::new(dir)))
+            }
+            KIND_FILE => {
+                let mut file = File::default();
+                file.set_name(name);
+                file.set_creation_time(creation_time);
+                file.set_metadata(metadata);
+                file.set_type_(type_from_code(self.u8()?)?);
+                let content_len = self.u64()? as usize;
+                file.set_content(self.take(content_len)?.to_vec());
+                Ok(Node::File(file))
+            }
+            KIND_SYMLINK => {
+                let target = self.string()?;
+                let mut link = Symlink::new(Path::new(&name), Path::new(&target), creation_time)?;
+                link.set_metadata(metadata);
+                Ok(Node::Symlink(link))
+            }
+            other => Err(CustomError::InvalidSnapshot(format!("unknown entry kind {}", other))),
+        }
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::file_system::dir::ScanOptions;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn damaged_snapshots_are_rejected() -> Result<(), CustomError> {
+        let snapshot = std::env::temp_dir().join(format!("snapshot_damaged_{}.snap", std::process::id()));
+        let root = Dir::new_from_dir(Path::new("root"), 0)?;
+        save(&root, &snapshot)?;
+        let saved = fs::read(&snapshot)?;
+        let load_with = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = saved.clone();
+            change(&mut bytes);
+            fs::write(&snapshot, bytes).unwrap();
+            match load(&snapshot) {
+                Err(CustomError::InvalidSnapshot(reason)) => reason,
+                _ => panic!("the snapshot should be rejected"),
+            }
+        };
+        assert_eq!(load_with(&|bytes| bytes[0] = b'X'), "not a snapshot file");
+        assert_eq!(load_with(&|bytes| bytes[8] = 2), "unsupported version 2");
+        assert_eq!(load_with(&|bytes| { bytes.pop(); }), "truncated body");
+        assert_eq!(load_with(&|bytes| *bytes.last_mut().unwrap() ^= 1), "checksum mismatch");
+        fs::remove_file(&snapshot)?;
+        Ok(())
+    }
+
+    #[test]
+    fn save_and_load_keep_links_and_the_uncached_content() -> Result<(), CustomError> {
+        let dir = std::env::temp_dir().join(format!("snapshot_round_trip_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub"))?;
+        fs::write(dir.join("sub/big.txt"), vec![b'x'; 100])?;
+        let name = dir.to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+        let mut root = Dir::scan(name, &ScanOptions { content_cap: 4, ..ScanOptions::default() })?;
+        root.new_symlink(&dir.join("link"), Path::new("sub/big.txt"))?;
+        let snapshot = std::env::temp_dir().join(format!("snapshot_round_trip_{}.snap", std::process::id()));
+        save(&root, &snapshot)?;
+        fs::remove_dir_all(&dir)?;
+        let loaded = load(&snapshot);
+        fs::remove_file(&snapshot)?;
+        let loaded = loaded?;
+
+        match loaded.get_node(&dir.join("sub/big.txt")) {
+            Some(Node::File(file)) => {
+                assert_eq!(file.get_size(), 100);
+                assert_eq!(*file.read_content()?, vec![b'x'; 100]);
+            }
+            _ => panic!("the file is missing"),
+        }
+        match loaded.get_node(&dir.join("link")) {
+            Some(Node::Symlink(link)) => assert_eq!(link.get_target(), PathBuf::from("sub/big.txt")),
+            _ => panic!("the link is missing"),
+        }
+        Ok(())
+    }
+}
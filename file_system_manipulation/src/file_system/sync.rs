@@ -0,0 +1,208 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::file_system::dir::{Dir, Node};
+use crate::file_system::dir::file::{CustomError, File};
+use crate::file_system::dir::symlink::Symlink;
+
+pub enum SyncAction {
+    CreateDir(PathBuf),
+    WriteFile(PathBuf),
+    RemoveDir(PathBuf),
+    RemoveFile(PathBuf),
+    CreateSymlink(PathBuf, PathBuf), // (link, target)
+}
+impl Display for SyncAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncAction::CreateDir(path) => write!(f, "create dir  {}", path.display()),
+            SyncAction::WriteFile(path) => write!(f, "write file  {}", path.display()),
+            SyncAction::RemoveDir(path) => write!(f, "remove dir  {}", path.display()),
+            SyncAction::RemoveFile(path) => write!(f, "remove file {}", path.display()),
+            SyncAction::CreateSymlink(path, target) => write!(f, "create link {} -> {}", path.display(), target.display()),
+        }
+    }
+}
+
+fn leaf_name(name: &str) -> Result<&std::ffi::OsStr, CustomError> {
+    Path::new(name).file_name().ok_or(CustomError::FileOrDirNameNotFound)
+}
+
+/* Makes the disk directory 'target' mirror 'dir': missing dirs are created, files whose content differs are
+   (re)written and entries which are not in the virtual tree are deleted. With dry_run nothing is touched,
+   the actions are only collected. */
+pub fn sync_dir(dir: &Dir, target: &Path, dry_run: bool, actions: &mut Vec<SyncAction>) -> Result<(), CustomError> {
+    sync_below(dir, target, 0, dry_run, actions)
+}
+
+// 'depth' is the number of dirs from the root being synced down to 'dir', needed to write the links' targets
+fn sync_below(dir: &Dir, target: &Path, depth: usize, dry_run: bool, actions: &mut Vec<SyncAction>) -> Result<(), CustomError> {
+    // below the root a file in the way has already been removed by the parent, even if only in a dry run
+    if depth == 0 && target.is_file() {
+        apply(SyncAction::RemoveFile(target.to_path_buf()), dry_run, actions)?;
+    }
+    if !target.is_dir() {
+        apply(SyncAction::CreateDir(target.to_path_buf()), dry_run, actions)?;
+        set_creation_time(target, dir.get_creation_time(), dry_run)?;
+    } else {
+        for entry in fs::read_dir(target)? {
+            let entry = entry?;
+            let entry_type = entry.file_type()?; // links are not followed, they are compared as links
+            let keep = dir.get_children().iter().any(|child| {
+                leaf_name(child.get_name()).is_ok_and(|name| name == entry.file_name()) && match child {
+                    Node::Dir(_) => entry_type.is_dir(),
+                    Node::File(_) => entry_type.is_file(),
+                    Node::Symlink(child) => entry_type.is_symlink() && fs::read_link(entry.path()).is_ok_and(|target| target == child.disk_target(depth)),
+                }
+            });
+            if !keep {
+                let action = if entry_type.is_dir() { SyncAction::RemoveDir(entry.path()) } else { SyncAction::RemoveFile(entry.path()) };
+                apply(action, dry_run, actions)?;
+            }
+        }
+    }
+    for child in dir.get_children() {
+        match child {
+            Node::Dir(child) => sync_below(child, &target.join(leaf_name(child.get_name())?), depth + 1, dry_run, actions)?,
+            Node::File(child) => sync_file(child, &target.join(leaf_name(child.get_name())?), dry_run, actions)?,
+            Node::Symlink(child) => sync_symlink(child, &target.join(leaf_name(child.get_name())?), depth, dry_run, actions)?,
+        }
+    }
+    Ok(())
+}
+
+fn sync_file(file: &File, target: &Path, dry_run: bool, actions: &mut Vec<SyncAction>) -> Result<(), CustomError> {
+    let content = file.read_content()?;
+    if target.is_file() && fs::read(target)? == *content {
+        return Ok(());
+    }
+    apply(SyncAction::WriteFile(target.to_path_buf()), dry_run, actions)?;
+    if !dry_run {
+        fs::write(target, &content)?;
+    }
+    set_creation_time(target, file.get_creation_time(), dry_run)
+}
+
+/* A link which already points to the right target has been kept by sync_below, any other one has been removed.
+   The target is checked again rather than the link only, as a dry run leaves the removed links on disk. */
+fn sync_symlink(link: &Symlink, target: &Path, depth: usize, dry_run: bool, actions: &mut Vec<SyncAction>) -> Result<(), CustomError> {
+    let link_target = link.disk_target(depth);
+    if fs::read_link(target).is_ok_and(|current| current == link_target) {
+        return Ok(());
+    }
+    apply(SyncAction::CreateSymlink(target.to_path_buf(), link_target), dry_run, actions)
+}
+
+fn apply(action: SyncAction, dry_run: bool, actions: &mut Vec<SyncAction>) -> Result<(), CustomError> {
+    if !dry_run {
+        match &action {
+            SyncAction::CreateDir(path) => fs::create_dir(path)?,
+            SyncAction::RemoveDir(path) => fs::remove_dir_all(path)?,
+            SyncAction::RemoveFile(path) => fs::remove_file(path)?,
+            SyncAction::CreateSymlink(path, target) => create_symlink(path, target)?,
+            SyncAction::WriteFile(_) => {}, // written by sync_file, which owns the content
+        }
+    }
+    actions.push(action);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(path: &Path, target: &Path) -> Result<(), CustomError> {
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(())
+}
+#[cfg(target_os = "windows")]
+fn create_symlink(path: &Path, target: &Path) -> Result<(), CustomError> {
+    // windows needs to know the kind of the target, which is resolved from the dir of the link
+    let resolved = path.parent().map(|parent| parent.join(target)).unwrap_or_else(|| target.to_path_buf());
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, path)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn set_creation_time(path: &Path, creation_time: u64, dry_run: bool) -> Result<(), CustomError> {
+    use std::os::windows::fs::{FileTimesExt, OpenOptionsExt};
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x02000000; // needed to open directories too
+    if dry_run {
+        return Ok(());
+    }
+    let handle = fs::OpenOptions::new().write(true).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(path)?;
+    let created = std::time::UNIX_EPOCH + std::time::Duration::from_secs(creation_time);
+    handle.set_times(fs::FileTimes::new().set_created(created))?;
+    Ok(())
+}
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_creation_time(path: &Path, creation_time: u64, dry_run: bool) -> Result<(), CustomError> {
+    #[cfg(target_os = "macos")]
+    use std::os::macos::fs::FileTimesExt;
+    #[cfg(target_os = "ios")]
+    use std::os::ios::fs::FileTimesExt;
+    if dry_run {
+        return Ok(());
+    }
+    // a read-only handle is enough to change the times, and it opens directories too
+    let handle = fs::File::open(path)?;
+    let created = std::time::UNIX_EPOCH + std::time::Duration::from_secs(creation_time);
+    handle.set_times(fs::FileTimes::new().set_created(created))?;
+    Ok(())
+}
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+fn set_creation_time(_path: &Path, _creation_time: u64, _dry_run: bool) -> Result<(), CustomError> {
+    // the creation (birth) time cannot be set on the other unix-like systems
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use std::os::unix::net::UnixListener;
+    use super::*;
+    use crate::file_system::dir::ScanOptions;
+    use crate::file_system::dir::tests::{scratch_dir, tree};
+
+    #[test]
+    fn targets_from_the_root_are_relative_on_disk() {
+        let disk = scratch_dir("sync_links");
+        let mut root = tree(&["a/", "a/x.txt", "b/", "b/c/"]);
+        root.new_symlink(Path::new("root/b/c/link"), Path::new("/a/x.txt")).unwrap();
+        root.new_symlink(Path::new("root/to_root"), Path::new("/")).unwrap();
+        sync_dir(&root, &disk, false, &mut vec![]).unwrap();
+        assert_eq!(fs::read_link(disk.join("b/c/link")).unwrap(), Path::new("../../a/x.txt"));
+        assert_eq!(fs::read_to_string(disk.join("b/c/link")).unwrap(), "a/x.txt");
+        assert_eq!(fs::read_link(disk.join("to_root")).unwrap(), Path::new("."));
+        // the links already right are kept
+        let mut actions = vec![];
+        sync_dir(&root, &disk, true, &mut actions).unwrap();
+        assert!(actions.is_empty());
+        fs::remove_dir_all(disk).unwrap();
+    }
+
+    #[test]
+    fn absolute_targets_on_disk_are_translated_when_scanned() {
+        let disk = scratch_dir("scan_links");
+        let outside = scratch_dir("scan_links_outside");
+        fs::create_dir(disk.join("a")).unwrap();
+        fs::write(disk.join("a/x.txt"), "x").unwrap();
+        symlink(disk.join("a/x.txt"), disk.join("a/inside")).unwrap();
+        symlink(outside.join("y.txt"), disk.join("a/outside")).unwrap();
+        let _socket = UnixListener::bind(disk.join("socket")).unwrap();
+        let mut skipped = vec![];
+        let root = Dir::scan_with_skipped(disk.to_str().unwrap(), &ScanOptions::default(), &mut skipped).unwrap();
+        let target = |name: &str| match root.get_node(&disk.join(name)) {
+            Some(Node::Symlink(link)) => link.get_target().to_path_buf(),
+            _ => panic!("{} is not a link", name),
+        };
+        assert_eq!(target("a/inside"), Path::new("/a/x.txt"));
+        let outside_target = target("a/outside");
+        assert!(outside_target.is_relative());
+        assert_eq!(fs::canonicalize(disk.join("a").join(&outside_target).parent().unwrap()).unwrap(), fs::canonicalize(&outside).unwrap());
+        assert_eq!(skipped, [disk.join("socket")]);
+        fs::remove_dir_all(disk).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+}
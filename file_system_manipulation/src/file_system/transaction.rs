@@ -0,0 +1,32 @@
+use crate::file_system::{CopyOptions, FileSystem};
+use crate::file_system::dir::RemoveSummary;
+use crate::file_system::dir::file::{CustomError, File};
+
+/* The changes a FileSystem::transaction can make. They are applied to the tree right away, so that each one sees
+   the previous ones, and reverted all together if the transaction fails. Reads go through get_fs. */
+pub struct Transaction<'a> {
+    fs: &'a mut FileSystem,
+}
+impl<'a> Transaction<'a> {
+    pub fn new(fs: &'a mut FileSystem) -> Transaction<'a> {
+        Transaction { fs }
+    }
+    pub fn get_fs(&self) -> &FileSystem { self.fs }
+
+    pub fn cd(&mut self, path: &str) -> Result<(), CustomError> { self.fs.cd(path) }
+    // the root of a new FileSystem is not journaled, so it cannot be made here
+    pub fn mk_dir(&mut self, path: &str) -> Result<(), CustomError> {
+        if self.fs.root.get_name().is_empty() {
+            return Err(CustomError::NoRoot);
+        }
+        self.fs.mk_dir(path)
+    }
+    pub fn rm_dir(&mut self, path: &str) -> Result<(), CustomError> { self.fs.rm_dir(path) }
+    pub fn rm_dir_all(&mut self, path: &str) -> Result<RemoveSummary, CustomError> { self.fs.rm_dir_all(path) }
+    pub fn new_file(&mut self, path: &str, file: File) -> Result<(), CustomError> { self.fs.new_file(path, file) }
+    pub fn symlink(&mut self, path: &str, target: &str) -> Result<(), CustomError> { self.fs.symlink(path, target) }
+    pub fn rm_file(&mut self, path: &str) -> Result<(), CustomError> { self.fs.rm_file(path) }
+    pub fn mv(&mut self, src: &str, dst: &str) -> Result<(), CustomError> { self.fs.mv(src, dst) }
+    pub fn copy(&mut self, src: &str, dst: &str, recursive: bool) -> Result<(), CustomError> { self.fs.copy(src, dst, recursive) }
+    pub fn copy_with(&mut self, src: &str, dst: &str, options: &CopyOptions) -> Result<(), CustomError> { self.fs.copy_with(src, dst, options) }
+}
@@ -0,0 +1,24 @@
+use std::fmt::{Display, Formatter};
+use crate::file_system::dir::Dir;
+
+/* A named copy of the tree. Copying is cheap: dirs are shared with the FileSystem, as well as the content of the
+   files, and a dir is copied only when one of the two trees changes something below it. */
+#[derive(Clone)]
+pub struct Version {
+    name: String,
+    creation_time: u64,
+    root: Dir,
+}
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (creation_time={})", self.name, self.creation_time)
+    }
+}
+impl Version {
+    pub fn new(name: &str, creation_time: u64, root: &Dir) -> Version {
+        Version { name: name.to_string(), creation_time, root: root.clone() }
+    }
+    pub fn get_name(&self) -> &str { &self.name }
+    pub fn get_creation_time(&self) -> u64 { self.creation_time }
+    pub fn get_root(&self) -> &Dir { &self.root }
+}
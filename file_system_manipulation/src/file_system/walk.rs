@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use crate::file_system::dir::{Dir, Node};
+
+/* Iterator over every node below a Dir, yielding (full path, depth, node). The children of the starting
+   Dir have depth 1. By default the tree is walked depth-first in pre-order (a dir before its content). */
+pub struct Walk<'a> {
+    start: Option<&'a Dir>, // children not queued yet, the order depends on the options
+    pending: VecDeque<(usize, &'a Node, bool)>, // (depth, node, children already queued)
+    breadth_first: bool,
+    post_order: bool,
+    max_depth: Option<usize>,
+    prune: Option<PruneFn<'a>>,
+}
+type PruneFn<'a> = Box<dyn Fn(&Path, usize, &Node) -> bool + 'a>;
+impl<'a> Walk<'a> {
+    pub fn new(dir: &'a Dir) -> Walk<'a> {
+        Walk {
+            start: Some(dir),
+            pending: VecDeque::new(),
+            breadth_first: false,
+            post_order: false,
+            max_depth: None,
+            prune: None,
+        }
+    }
+    // level by level, closest nodes first
+    pub fn breadth_first(mut self) -> Self {
+        self.breadth_first = true;
+        self
+    }
+    // a dir after its content, ignored when walking breadth-first
+    pub fn post_order(mut self) -> Self {
+        self.post_order = true;
+        self
+    }
+    // nodes deeper than max_depth are not visited
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+    // dirs for which 'prune' returns true are still yielded, but their content is skipped
+    pub fn prune<F: Fn(&Path, usize, &Node) -> bool + 'a>(mut self, prune: F) -> Self {
+        self.prune = Some(Box::new(prune));
+        self
+    }
+    fn children_of(&self, depth: usize, node: &'a Node) -> Option<&'a Vec<Node>> {
+        match node {
+            Node::Dir(dir) if self.max_depth.is_none_or(|max| depth < max)
+                && !self.prune.as_ref().is_some_and(|prune| prune(Path::new(dir.get_name()), depth, node)) => Some(dir.get_children()),
+            _ => None
+        }
+    }
+}
+impl<'a> Iterator for Walk<'a> {
+    type Item = (&'a Path, usize, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the children of the start dir have depth 1, so none is visited with a max_depth of 0
+        if let Some(start) = self.start.take().filter(|_| self.max_depth.is_none_or(|max| max > 0)) {
+            if self.breadth_first {
+                self.pending.extend(start.get_children().iter().map(|child| (1, child, false)));
+            } else {
+                self.pending.extend(start.get_children().iter().rev().map(|child| (1, child, false)));
+            }
+        }
+        loop {
+            let (depth, node, expanded) = if self.breadth_first { self.pending.pop_front()? } else { self.pending.pop_back()? };
+            let children = if expanded { None } else { self.children_of(depth, node) };
+            if let Some(children) = children {
+                if self.breadth_first {
+                    self.pending.extend(children.iter().map(|child| (depth + 1, child, false)));
+                } else {
+                    if self.post_order {
+                        // visited again, to be yielded, once all its content has been
+                        self.pending.push_back((depth, node, true));
+                    }
+                    // reversed, so that the first child is the first one popped
+                    self.pending.extend(children.iter().rev().map(|child| (depth + 1, child, false)));
+                    if self.post_order {
+                        continue;
+                    }
+                }
+            }
+            return Some((Path::new(node.get_name()), depth, node));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::dir::tests::tree;
+
+    fn visited(walk: Walk) -> Vec<(String, usize)> {
+        walk.map(|(path, depth, _)| (path.to_string_lossy().trim_start_matches("root/").to_string(), depth)).collect()
+    }
+
+    #[test]
+    fn orders_depths_and_pruning() {
+        let root = tree(&["a/", "a/x", "a/b/", "a/b/y", "c"]);
+        let pairs = |expected: &[(&str, usize)]| expected.iter().map(|&(path, depth)| (path.to_string(), depth)).collect::<Vec<_>>();
+        assert_eq!(visited(Walk::new(&root)), pairs(&[("a", 1), ("a/x", 2), ("a/b", 2), ("a/b/y", 3), ("c", 1)]));
+        assert_eq!(visited(Walk::new(&root).post_order()), pairs(&[("a/x", 2), ("a/b/y", 3), ("a/b", 2), ("a", 1), ("c", 1)]));
+        assert_eq!(visited(Walk::new(&root).breadth_first()), pairs(&[("a", 1), ("c", 1), ("a/x", 2), ("a/b", 2), ("a/b/y", 3)]));
+        assert_eq!(visited(Walk::new(&root).max_depth(1)), pairs(&[("a", 1), ("c", 1)]));
+        assert!(visited(Walk::new(&root).max_depth(0)).is_empty());
+        let pruned = Walk::new(&root).prune(|path, _, _| path.ends_with("b"));
+        assert_eq!(visited(pruned), pairs(&[("a", 1), ("a/x", 2), ("a/b", 2), ("c", 1)]));
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use notify::event::{ModifyKind, RenameMode};
+use crate::file_system::FileSystem;
+use crate::file_system::diff::{diff, Change, DiffOptions};
+use crate::file_system::dir::{Dir, Node, ScanOptions};
+use crate::file_system::dir::file::{CustomError, File, NodeMetadata};
+use crate::file_system::dir::symlink::Symlink;
+use crate::file_system::walk::Walk;
+
+// a change applied to the tree, with the full paths of the nodes as stored in the tree
+#[derive(Clone, Debug, PartialEq)]
+pub enum FsEvent {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf),
+    Renamed(PathBuf, PathBuf), // (from, to)
+}
+impl Display for FsEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsEvent::Created(path) => write!(f, "created  {}", path.display()),
+            FsEvent::Removed(path) => write!(f, "removed  {}", path.display()),
+            FsEvent::Modified(path) => write!(f, "modified {}", path.display()),
+            FsEvent::Renamed(from, to) => write!(f, "renamed  {} -> {}", from.display(), to.display()),
+        }
+    }
+}
+
+/* Keeps a FileSystem scanned from a disk directory up to date with it (inotify on linux). The disk events are
+   queued by a background thread and applied to the tree only when sync or wait is called, so that the tree is
+   never changed while it is borrowed. Every applied change is also sent to all the subscribers. */
+pub struct Watcher {
+    _watcher: RecommendedWatcher, // the disk is watched until it is dropped
+    events: Receiver<notify::Result<Event>>,
+    disk_root: PathBuf,
+    options: ScanOptions, // used to read the new and changed nodes
+    subscribers: Vec<Sender<FsEvent>>,
+}
+impl Watcher {
+    pub fn new(root: &Dir, options: ScanOptions) -> Result<Watcher, CustomError> {
+        let disk_root = fs::canonicalize(root.get_name())?;
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&disk_root, RecursiveMode::Recursive)?;
+        Ok(Watcher {
+            _watcher: watcher,
+            events,
+            disk_root,
+            options,
+            subscribers: vec![],
+        })
+    }
+    // the stream ends when the Watcher is dropped
+    pub fn subscribe(&mut self) -> Receiver<FsEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+    // applies the events received so far, without waiting for new ones
+    pub fn sync(&mut self, fs: &mut FileSystem) -> Result<Vec<FsEvent>, CustomError> {
+        let mut changes = vec![];
+        self.drain(fs, &mut changes)?;
+        self.publish(fs, &changes);
+        Ok(changes)
+    }
+    // waits up to 'timeout' for the tree to change, then applies all the events received so far
+    pub fn wait(&mut self, fs: &mut FileSystem, timeout: Duration) -> Result<Vec<FsEvent>, CustomError> {
+        let deadline = Instant::now() + timeout;
+        let mut changes = vec![];
+        while changes.is_empty() {
+            match self.events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => self.apply(fs, event?, &mut changes)?,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(CustomError::WatchError(notify::Error::generic("watcher stopped"))),
+            }
+            self.drain(fs, &mut changes)?;
+        }
+        self.publish(fs, &changes);
+        Ok(changes)
+    }
+
+    fn drain(&self, fs: &mut FileSystem, changes: &mut Vec<FsEvent>) -> Result<(), CustomError> {
+        while let Ok(event) = self.events.try_recv() {
+            self.apply(fs, event?, changes)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, fs: &mut FileSystem, changes: &[FsEvent]) {
+        if changes.is_empty() {
+            return;
+        }
+        fs.fix_cwd();
+        self.subscribers.retain(|subscriber| changes.iter().all(|change| subscriber.send(change.clone()).is_ok()));
+    }
+
+    fn apply(&self, fs: &mut FileSystem, event: Event, changes: &mut Vec<FsEvent>) -> Result<(), CustomError> {
+        if event.need_rescan() {
+            return self.rescan(fs, changes);
+        }
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                self.rename(fs, &event.paths[0], &event.paths[1], changes)
+            }
+            EventKind::Access(_) => Ok(()),
+            // anything else, also a single side of a rename, is applied by comparing the tree with the disk
+            _ => event.paths.iter().try_for_each(|path| self.refresh(fs, path, changes)),
+        }
+    }
+
+    /* Events were lost, e.g. the queue of the kernel overflowed, and the event asking for a rescan names no path:
+       the whole dir is scanned again and what differs from the tree is sent as changes. */
+    fn rescan(&self, fs: &mut FileSystem, changes: &mut Vec<FsEvent>) -> Result<(), CustomError> {
+        let disk_name = self.disk_root.to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+        let mut root = Node::Dir(Arc::new(Dir::scan(disk_name, &self.options)?));
+        root.set_path(Path::new(fs.root.get_name()))?;
+        let Node::Dir(root) = root else { return Ok(()) };
+        for change in diff(&fs.root, &root, &DiffOptions::default())?.get_changes() {
+            match change {
+                Change::Added(path) => created(root.get_node(path), path, changes),
+                Change::Removed(path) => changes.push(FsEvent::Removed(path.clone())),
+                Change::Modified(path, _) | Change::TypeChanged(path, _, _) => changes.push(FsEvent::Modified(path.clone())),
+            }
+        }
+        fs.root = Arc::unwrap_or_clone(root);
+        Ok(())
+    }
+
+    // the path of the node in the tree, None for the root itself and for paths outside of it
+    fn tree_path(&self, fs: &FileSystem, disk_path: &Path) -> Option<PathBuf> {
+        let relative = disk_path.strip_prefix(&self.disk_root).ok()?;
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+        Some(Path::new(fs.root.get_name()).join(relative))
+    }
+
+    /* On linux each side of a rename comes first as an event of its own, which is applied as a removal and a
+       creation, so the two changes are only merged here. Where the sides do not come alone they are applied now. */
+    fn rename(&self, fs: &mut FileSystem, from: &Path, to: &Path, changes: &mut Vec<FsEvent>) -> Result<(), CustomError> {
+        let (from_path, to_path) = (self.tree_path(fs, from), self.tree_path(fs, to));
+        let merge = |changes: &mut Vec<FsEvent>| {
+            let (Some(from_path), Some(to_path)) = (&from_path, &to_path) else { return false };
+            let removed = changes.iter().position(|change| *change == FsEvent::Removed(from_path.clone()));
+            let created = changes.iter().position(|change| *change == FsEvent::Created(to_path.clone()));
+            let (Some(removed), Some(_)) = (removed, created) else { return false };
+            changes[removed] = FsEvent::Renamed(from_path.clone(), to_path.clone());
+            // the content of a renamed dir is renamed along, not created
+            changes.retain(|change| !matches!(change, FsEvent::Created(path) if path.starts_with(to_path)));
+            true
+        };
+        if !merge(changes) {
+            self.refresh(fs, from, changes)?;
+            self.refresh(fs, to, changes)?;
+            merge(changes);
+        }
+        Ok(())
+    }
+
+    fn refresh(&self, fs: &mut FileSystem, disk_path: &Path, changes: &mut Vec<FsEvent>) -> Result<(), CustomError> {
+        let Some(path) = self.tree_path(fs, disk_path) else { return Ok(()) };
+        // the content of a dir already in the tree is refreshed by the events of its children
+        if let (Some(dir), Ok(metadata)) = (fs.root.get_dir_mut(&path), fs::metadata(disk_path)) {
+            if metadata.is_dir() {
+                dir.set_metadata(NodeMetadata::from_fs(&metadata));
+                modified(path, changes);
+                return Ok(());
+            }
+        }
+        let disk_node = match fs::symlink_metadata(disk_path).map(|metadata| self.read_node(disk_path, &path, metadata)) {
+            Ok(Ok(Some(node))) => Some(node),
+            Ok(Ok(None)) => return Ok(()),
+            // removed while being read, its own event follows
+            Ok(Err(CustomError::IoError(e))) | Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Ok(Err(e)) => return Err(e),
+            Err(e) => return Err(e.into()),
+        };
+        // a missing parent is created by its own event, which scans it whole
+        let Some(parent) = path.parent().and_then(|parent| fs.root.get_dir_mut(parent)) else { return Ok(()) };
+        match disk_node {
+            None => {
+                if parent.take_child(&path).is_some() {
+                    changes.push(FsEvent::Removed(path));
+                }
+            }
+            Some(node) => {
+                match parent.contains(&path) {
+                    true => modified(path, changes),
+                    false => created(Some(&node), &path, changes),
+                }
+                parent.add_child(node);
+            }
+        }
+        Ok(())
+    }
+
+    // None for what is neither a dir, a file nor a link, which a scan skips too
+    fn read_node(&self, disk_path: &Path, path: &Path, metadata: fs::Metadata) -> Result<Option<Node>, CustomError> {
+        let metadata = match fs::metadata(disk_path) {
+            Ok(target_metadata) if metadata.is_symlink() && self.options.follow_symlinks => target_metadata,
+            _ => metadata,
+        };
+        let disk_name = disk_path.to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+        let mut node = if metadata.is_dir() {
+            Node::Dir(Arc::new(Dir::scan(disk_name, &self.options)?))
+        } else if metadata.is_symlink() {
+            Node::Symlink(Symlink::read(disk_path, &self.disk_root)?)
+        } else if metadata.is_file() {
+            Node::File(File::new(disk_name.to_string(), metadata, self.options.content_cap)?)
+        } else {
+            return Ok(None);
+        };
+        node.set_path(path)?;
+        Ok(Some(node))
+    }
+}
+
+// a new dir is sent with all its content, which was scanned with it
+fn created(node: Option<&Node>, path: &Path, changes: &mut Vec<FsEvent>) {
+    changes.push(FsEvent::Created(path.to_path_buf()));
+    if let Some(Node::Dir(dir)) = node {
+        changes.extend(Walk::new(dir).map(|(path, _, _)| FsEvent::Created(path.to_path_buf())));
+    }
+}
+
+// the events of what was just created, e.g. the files scanned with their new dir, do not make it modified too
+fn modified(path: PathBuf, changes: &mut Vec<FsEvent>) {
+    if !changes.contains(&FsEvent::Created(path.clone())) {
+        changes.push(FsEvent::Modified(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::Flag;
+    use super::*;
+    use crate::file_system::dir::tests::scratch_dir;
+
+    #[test]
+    fn a_rescan_sends_what_changed_since_the_tree_was_read() {
+        let disk = scratch_dir("watch_rescan");
+        fs::write(disk.join("changed.txt"), "before").unwrap();
+        fs::write(disk.join("gone.txt"), "gone").unwrap();
+        fs::write(disk.join("kept.txt"), "kept").unwrap();
+        let mut fs = FileSystem::from_dir(disk.to_str().unwrap()).unwrap();
+        let watcher = fs.watch(&ScanOptions::default()).unwrap();
+        fs::write(disk.join("changed.txt"), "after, longer").unwrap();
+        fs::remove_file(disk.join("gone.txt")).unwrap();
+        fs::create_dir(disk.join("new")).unwrap();
+        fs::write(disk.join("new/x.txt"), "x").unwrap();
+        let mut changes = vec![];
+        watcher.apply(&mut fs, Event::new(EventKind::Other).set_flag(Flag::Rescan), &mut changes).unwrap();
+        let root = Path::new(fs.get_root().get_name());
+        assert_eq!(changes, [
+            FsEvent::Modified(root.join("changed.txt")),
+            FsEvent::Removed(root.join("gone.txt")),
+            FsEvent::Created(root.join("new")),
+            FsEvent::Created(root.join("new/x.txt")),
+        ]);
+        assert!(fs.get_node("/new/x.txt").is_some());
+        assert!(fs.get_node("/gone.txt").is_none());
+        fs::remove_dir_all(disk).unwrap();
+    }
+
+    #[test]
+    fn the_content_of_a_new_dir_is_created_once() {
+        let disk = scratch_dir("watch_new_dir");
+        let mut fs = FileSystem::from_dir(disk.to_str().unwrap()).unwrap();
+        let watcher = fs.watch(&ScanOptions::default()).unwrap();
+        fs::create_dir(disk.join("new")).unwrap();
+        fs::write(disk.join("new/x.txt"), "x").unwrap();
+        let mut changes = vec![];
+        for path in [disk.join("new"), disk.join("new/x.txt")] {
+            watcher.apply(&mut fs, Event::new(EventKind::Create(notify::event::CreateKind::Any)).add_path(path), &mut changes).unwrap();
+        }
+        let root = Path::new(fs.get_root().get_name());
+        assert_eq!(changes, [FsEvent::Created(root.join("new")), FsEvent::Created(root.join("new/x.txt"))]);
+        fs::remove_dir_all(disk).unwrap();
+    }
+}
@@ -1,6 +1,10 @@
-mod file_system;
-use crate::file_system::FileSystem;
-use crate::file_system::dir::file::{CustomError, File, FileType};
+use std::sync::Arc;
+use std::thread;
+use file_system_manipulation::file_system::{CopyOptions, FileSystem};
+use file_system_manipulation::file_system::diff::DiffOptions;
+use file_system_manipulation::file_system::shared::SharedFileSystem;
+use file_system_manipulation::file_system::dir::ScanOptions;
+use file_system_manipulation::file_system::dir::file::{CustomError, File, FileType, TextEncoding};
 
 fn main() -> Result<(), CustomError>{
     //1) create an empty fs
@@ -11,38 +15,245 @@ fn main() -> Result<(), CustomError>{
     println!("File System CREATED from Directory 'my_fs':\n{}", my_fs.get_root());
 
     //3) make a dir in the specified path
-    my_fs.mk_dir("my_fs\\folder0_0\\folder_new")?;
-    println!("File System Directory ADDED 'my_fs\\folder0_0\\folder_new':\n{}", my_fs.get_root());
+    my_fs.mk_dir("my_fs/folder0_0/folder_new")?;
+    println!("File System Directory ADDED 'my_fs/folder0_0/folder_new':\n{}", my_fs.get_root());
 
     //3) delete the dir specified by the path
-    my_fs.rm_dir("my_fs\\folder0_0\\folder_new")?;
-    println!("File System Directory DELETED 'my_fs\\folder0_0\\folder_new':\n{}", my_fs.get_root());
+    my_fs.rm_dir("my_fs/folder0_0/folder_new")?;
+    println!("File System Directory DELETED 'my_fs/folder0_0/folder_new':\n{}", my_fs.get_root());
 
     //4) create a file from a specified one
     let mut test_file = File::default();
     test_file.set_name("test_file.txt".to_string());
     test_file.set_content(vec![22; 1000]);
     test_file.set_creation_time(123456789);
-    test_file.set_type_(FileType::Text);
-    my_fs.mk_dir("my_fs\\folder0_0\\test_folder")?;
-    my_fs.new_file("my_fs\\folder0_0\\test_folder\\test_file.txt", test_file)?;
-    println!("File System File CREATED 'my_fs\\folder0_0\\test_folder\\test_file.txt':\n{}", my_fs.get_root());
+    test_file.set_type_(FileType::Text(TextEncoding::Utf8));
+    my_fs.mk_dir("my_fs/folder0_0/test_folder")?;
+    my_fs.new_file("my_fs/folder0_0/test_folder/test_file.txt", test_file)?;
+    println!("File System File CREATED 'my_fs/folder0_0/test_folder/test_file.txt':\n{}", my_fs.get_root());
 
     //6) get the file specified by the path
-    let file_found = my_fs.get_file("my_fs\\folder0_0\\test_folder\\test_file.txt");
+    let file_found = my_fs.get_file("my_fs/folder0_0/test_folder/test_file.txt");
     match file_found {
         Some(file) => println!("File System File GOT:\n{}", file),
         None => println!("File not Found!\n")
     }
 
     //5) remove the file specified by the path
-    my_fs.rm_file("my_fs\\folder0_0\\test_folder\\test_file.txt")?;
-    println!("File System File DELETED 'my_fs\\folder0_0\\test_folder\\test_file.txt':\n{}", my_fs.get_root());
+    my_fs.rm_file("my_fs/folder0_0/test_folder/test_file.txt")?;
+    println!("File System File DELETED 'my_fs/folder0_0/test_folder/test_file.txt':\n{}", my_fs.get_root());
 
     //7) query the fs (eventually with different queries)
-    let queries = vec!["name:file0_0.txt", "content:test queries"];
+    let queries = vec!["name:file0_0.txt", "content:\"test queries\""];
     let res = my_fs.search(&queries);
     println!("{}", res);
 
+    //7b) move (and rename) a dir, all its content follows it
+    my_fs.mv("my_fs/folder0_0/test_folder", "my_fs/moved_folder")?;
+    println!("File System Directory MOVED to 'my_fs/moved_folder':\n{}", my_fs.get_root());
+    if let Err(e) = my_fs.mv("my_fs/folder0_0", "my_fs/folder0_0/inner") {
+        println!("Cannot move 'my_fs/folder0_0' into 'my_fs/folder0_0/inner': {}", e);
+    }
+
+    //7c) copy a whole dir, the copy keeps the original creation times
+    my_fs.copy_with("my_fs/folder0_0", "my_fs/folder0_0_copy", &CopyOptions { recursive: true, preserve_creation_time: true })?;
+    my_fs.copy("my_fs/file0_0.txt", "my_fs/folder0_0_copy/file0_0_copy.txt", false)?;
+    println!("File System Directory COPIED to 'my_fs/folder0_0_copy':\n{}", my_fs.get_root());
+
+    //7d) remove a populated dir, which needs a recursive removal
+    if let Err(e) = my_fs.rm_dir("my_fs/folder0_0_copy") {
+        println!("Cannot remove 'my_fs/folder0_0_copy': {}", e);
+    }
+    let summary = my_fs.rm_dir_all("my_fs/folder0_0_copy")?;
+    println!("File System Directory DELETED 'my_fs/folder0_0_copy', {}:\n{}", summary, my_fs.get_root());
+
+    //7e) move around the fs and use paths relative to the current directory
+    my_fs.cd("folder0_0")?;
+    my_fs.mk_dir("./relative_folder/")?;
+    my_fs.mv("relative_folder", "../moved_folder//relative_folder")?;
+    my_fs.cd("../moved_folder")?;
+    println!("File System current directory '{}':\n{}", my_fs.pwd(), my_fs.get_root());
+    my_fs.rm_dir("/moved_folder/relative_folder")?;
+    my_fs.cd("..")?;
+
+    //7f) walk the fs: breadth-first without entering 'folder0_0', then depth-first with dirs after their content
+    for (path, depth, _node) in my_fs.walk().breadth_first().prune(|path, _, _| path.ends_with("folder0_0")) {
+        println!("{}{}", "  ".repeat(depth - 1), path.display());
+    }
+    for (path, depth, _node) in my_fs.walk().post_order().max_depth(2) {
+        println!("{}{}", "  ".repeat(depth - 1), path.display());
+    }
+
+    //8) query only a subtree of the fs, descending at most 1 level
+    let queries = vec!["name:file0_1.bin"];
+    let res = my_fs.search_from("my_fs/folder0_0", &queries, Some(1))?;
+    println!("{}", res);
+
+    //9) query the fs combining conditions with AND / OR / NOT and parentheses
+    let queries = vec!["name:file AND (larger:5 OR newer:1680000000) AND NOT content:draft"];
+    let res = my_fs.search(&queries);
+    println!("{}", res);
+
+    //10) query the fs reporting malformed queries instead of skipping them
+    match my_fs.try_search(&["name:file0_0.txt OR larger:abc"]) {
+        Ok(res) => println!("{}", res),
+        Err(e) => println!("{}", e)
+    }
+
+    //11) query the fs by name patterns: globs and (quoted) regexes, on leaf names or full paths
+    let queries = vec!["glob:**/file0_[0-9].bin", "regex:\"^file0_\\d+\\.(txt|md)$\""];
+    let res = my_fs.try_search(&queries)?;
+    println!("{}", res);
+
+    //12) list what writing the fs back to 'my_fs' would do, then actually write it in a temporary directory
+    for action in my_fs.sync_to_disk("my_fs", true)? {
+        println!("(dry run) {}", action);
+    }
+    let target = std::env::temp_dir().join("my_fs_synced");
+    let actions = my_fs.sync_to_disk(target.to_str().ok_or(CustomError::FileOrDirNameNotFound)?, false)?;
+    println!("File System SYNCED to '{}' with {} actions", target.display(), actions.len());
+
+    //13) save the fs in a single snapshot file and load it back
+    let snapshot = std::env::temp_dir().join("my_fs.snap");
+    let snapshot = snapshot.to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+    my_fs.save(snapshot)?;
+    let loaded_fs = FileSystem::load(snapshot)?;
+    println!("File System LOADED from snapshot '{}':\n{}", snapshot, loaded_fs.get_root());
+
+    //14) scan keeping at most 4 bytes of each file in memory, the rest is still reachable
+    let mut capped_fs = FileSystem::from_dir_with("my_fs", &ScanOptions { content_cap: 4, ..ScanOptions::default() })?;
+    println!("{}", capped_fs.try_search(&["content:queries AND larger:10"])?);
+    if let Some(file) = capped_fs.get_file("my_fs/file0_0.txt") {
+        println!("Cached {} of {} bytes: {:?}", file.get_content().len(), file.get_size(), file.get_content());
+        file.load_content()?;
+        println!("Cached {} of {} bytes after loading: {:?}", file.get_content().len(), file.get_size(), file.get_content());
+    }
+
+    //15) query the fs by the type detected from the content of the files
+    println!("{}", my_fs.try_search(&["type:utf-8", "type:binary"])?);
+
+    //16) query the fs by permissions, owner and modification time, read from the disk metadata
+    println!("{}", my_fs.try_search(&["perm:644", "owner:0", "modified-after:0"])?);
+
+    //17) links: paths are resolved through them, and once on disk they can be followed when scanning, a link
+    //    to one of the dirs being scanned (here, the root) is kept as a link instead of looping forever
+    my_fs.symlink("my_fs/folder_link", "folder0_0")?;
+    my_fs.symlink("my_fs/folder0_0/root_link", "..")?;
+    if let Some(file) = my_fs.get_file("my_fs/folder_link/root_link/file0_0.txt") {
+        println!("File GOT through links: {}", file.get_name());
+    }
+    my_fs.sync_to_disk(target.to_str().ok_or(CustomError::FileOrDirNameNotFound)?, false)?;
+    let followed_fs = FileSystem::from_dir_with(target.to_str().ok_or(CustomError::FileOrDirNameNotFound)?, &ScanOptions { follow_symlinks: true, ..ScanOptions::default() })?;
+    println!("File System SCANNED following links:\n{}", followed_fs.get_root());
+    my_fs.rm_file("my_fs/folder_link")?;
+    my_fs.rm_file("my_fs/folder0_0/root_link")?;
+
+    //18) watch a copy of 'my_fs': the changes made on the disk are applied to the tree and streamed to the subscribers
+    let watched_dir = std::env::temp_dir().join("my_fs_watched");
+    FileSystem::from_dir("my_fs")?.sync_to_disk(watched_dir.to_str().ok_or(CustomError::FileOrDirNameNotFound)?, false)?;
+    let mut watched_fs = FileSystem::from_dir(watched_dir.to_str().ok_or(CustomError::FileOrDirNameNotFound)?)?;
+    let mut watcher = watched_fs.watch(&ScanOptions::default())?;
+    let changes = watcher.subscribe();
+    let settle = std::time::Duration::from_millis(200);
+    std::fs::write(watched_dir.join("watched.txt"), "watched")?;
+    while !watcher.wait(&mut watched_fs, settle)?.is_empty() {}
+    std::fs::rename(watched_dir.join("watched.txt"), watched_dir.join("folder0_0/watched.txt"))?;
+    while !watcher.wait(&mut watched_fs, settle)?.is_empty() {}
+    println!("{}", watched_fs.try_search(&["name:watched"])?);
+    std::fs::remove_file(watched_dir.join("folder0_0/watched.txt"))?;
+    while !watcher.wait(&mut watched_fs, settle)?.is_empty() {}
+    for change in changes.try_iter() {
+        println!("File System WATCHED: {}", change);
+    }
+
+    //19) share a fs between threads: the readers search in parallel while the writers take turns
+    let shared_fs = Arc::new(SharedFileSystem::from(FileSystem::from_dir("my_fs")?));
+    let workers: Vec<_> = (0..4).map(|i| {
+        let shared_fs = Arc::clone(&shared_fs);
+        thread::spawn(move || -> Result<usize, CustomError> {
+            shared_fs.mk_dir(&format!("/thread_{}", i))?;
+            Ok(shared_fs.search(&["name:thread_"])?.len())
+        })
+    }).collect();
+    for worker in workers {
+        let found = worker.join().expect("worker panicked")?;
+        println!("File System SHARED: a thread found {} dirs made by the threads", found);
+    }
+    if let Some(file) = shared_fs.get_file("/file0_0.txt") {
+        println!("File System SHARED file GOT: {}", file.get_name());
+    }
+
+    //20) every change is journaled: undo a removal and a move, redo the move and print the history
+    my_fs.rm_dir_all("my_fs/folder0_0")?;
+    my_fs.mv("my_fs/file0_0.txt", "my_fs/moved_folder/file0_0.txt")?;
+    for _ in 0..2 {
+        if let Some(operation) = my_fs.undo()? {
+            println!("File System UNDONE: {}", operation);
+        }
+    }
+    if let Some(operation) = my_fs.redo()? {
+        println!("File System REDONE: {}", operation);
+    }
+    println!("File System HISTORY:\n{}", my_fs.get_journal());
+    println!("{}", my_fs.try_search(&["name:file0_"])?);
+
+    //21) transactions: either all their changes are applied or none is
+    my_fs.transaction(|tx| {
+        tx.mk_dir("/transaction_folder")?;
+        tx.copy("/file0_0.txt", "/transaction_folder/file0_0.txt", false)?;
+        tx.mv("/transaction_folder", "/moved_folder/transaction_folder")
+    })?;
+    let failed = my_fs.transaction(|tx| {
+        tx.rm_file("/moved_folder/transaction_folder/file0_0.txt")?;
+        tx.rm_dir("/moved_folder/missing_folder")
+    });
+    if let Err(e) = failed {
+        println!("File System TRANSACTION rolled back: {}", e);
+    }
+    println!("File System HISTORY:\n{}", my_fs.get_journal());
+    println!("{}", my_fs.try_search(&["name:transaction_folder"])?);
+
+    //22) named snapshots share the unchanged dirs and contents with the fs: change it, diff it and roll it back
+    my_fs.snapshot("before-import")?;
+    my_fs.transaction(|tx| {
+        tx.rm_dir_all("/moved_folder/transaction_folder")?;
+        tx.copy("/moved_folder", "/imported_folder", true)
+    })?;
+    if let Some(file) = my_fs.get_file("/file0_0.txt") {
+        file.set_content(b"changed after the snapshot".to_vec());
+    }
+    for version in my_fs.get_snapshots() {
+        println!("File System SNAPSHOT: {}", version);
+    }
+    for change in my_fs.diff_snapshot("before-import", &DiffOptions::default())?.get_changes() {
+        println!("File System CHANGED since 'before-import': {}", change);
+    }
+    my_fs.rollback("before-import")?;
+    println!("File System ROLLED BACK, {} changes left", my_fs.diff_snapshot("before-import", &DiffOptions::default())?.len());
+    my_fs.drop_snapshot("before-import")?;
+
+    //23) diff two scans of a copy of 'my_fs' taken before and after changing it, with the text diffs of the changed files
+    let diffed_dir = std::env::temp_dir().join("my_fs_diffed");
+    let diffed_name = diffed_dir.to_str().ok_or(CustomError::FileOrDirNameNotFound)?;
+    FileSystem::from_dir("my_fs")?.sync_to_disk(diffed_name, false)?;
+    std::fs::write(diffed_dir.join("diffed.txt"), "first line\nsecond line\nthird line\n")?;
+    let before = FileSystem::from_dir(diffed_name)?;
+    std::fs::write(diffed_dir.join("diffed.txt"), "first line\n2nd line\nthird line\nfourth line\n")?;
+    std::fs::write(diffed_dir.join("diffed.bin"), [0u8, 1, 2, 3])?;
+    let after = FileSystem::from_dir(diffed_name)?;
+    println!("File System DIFF of two scans:\n{}", before.diff(&after, &DiffOptions { text_diffs: true, ..DiffOptions::default() })?);
+
+    //24) group the files holding the same content by their hash, and look one of them up with a hash: query
+    my_fs.copy("/file0_0.txt", "/file0_0_copy.txt", false)?;
+    let mut same_content = File::default();
+    same_content.set_content(b"test queries".to_vec());
+    my_fs.new_file("/moved_folder/same_content.txt", same_content)?;
+    let duplicates = my_fs.find_duplicates();
+    println!("File System DUPLICATES:\n{}", duplicates);
+    if let Some(group) = duplicates.get_groups().first() {
+        let query = format!("hash:{}", &group.get_hash()[..12]);
+        println!("{}", my_fs.try_search(&[&query])?);
+    }
+
     Ok(())
 }